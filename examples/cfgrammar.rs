@@ -35,27 +35,16 @@ fn main() {
     file.read_to_string(&mut data).unwrap();
 
     // Parse sample file.
-    let mut new_set = true;
-    let mut sets: Vec<SampleSet> = Vec::new();
-    for line in data.lines() {
-        if line.len() < 2 {
-            new_set = true;
-            continue;
+    let sets = SampleSet::parse_blocks(&data).unwrap();
+    for set in sets.iter() {
+        let mut lowercase_set = SampleSet::with_labels(set.labels());
+        for sample in set.samples() {
+            if let Sample::Tokens(tokens) = sample {
+                lowercase_set.add_sample(Sample::Tokens(tokens.iter().map(|t| t.to_lowercase()).collect()));
+            }
         }
 
-        if new_set {
-            let labels: Vec<&str> = line.split(' ').filter(|t| t.len() > 0).collect();
-
-            sets.push(SampleSet::with_labels(&labels));
-            new_set = false;
-        } else {
-            sets.last_mut().unwrap().add_sample(
-                Sample::Tokens(line.split(' ').filter(|t| t.len() > 0).map(|t| t.to_owned().to_lowercase()).collect()),
-            );
-        }
-    }
-    for set in sets.iter() {
-        if let Err(e) = part.learn(set) {
+        if let Err(e) = part.learn(&lowercase_set) {
             eprintln!("{:?}: {}", set.labels(), e);
         }
     }
@@ -88,7 +77,7 @@ fn main() {
 
         print!("{result:<width$} ", result = ws.get_result(), width = 10);
         if n % 7 == 0 {
-            print!("\n")
+            println!()
         }
     }
 
@@ -32,9 +32,16 @@ fn main() {
     let mut file = File::open("./examples/res/markov.txt").unwrap();
     let mut data = String::with_capacity(2048);
     file.read_to_string(&mut data).unwrap();
-    for line in data.lines().filter(|l| l.len() > 1) {
+    let samples = SampleSet::parse_words(&data).unwrap();
+    for sample in samples.samples() {
+        let sample = match sample {
+            Sample::Word(word) => Sample::Word(word.to_lowercase()),
+            Sample::WordWeighted(word, weight) => Sample::WordWeighted(word.to_lowercase(), *weight),
+            Sample::Tokens(_) => continue,
+        };
+
         let mut sample_set = SampleSet::new();
-        sample_set.add_sample(Sample::Word(line.to_owned().to_lowercase()));
+        sample_set.add_sample(sample);
 
         if let Err(e) = part.learn(&sample_set) {
             eprintln!("{}", e);
@@ -82,7 +89,7 @@ fn main() {
 
         print!("{result:<width$} ", result = ws.get_result(), width = 10);
         if n % 7 == 0 {
-            print!("\n")
+            println!()
         }
     }
 
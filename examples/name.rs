@@ -1,4 +1,4 @@
-use namegen::{NamePart, FormattingRule, SampleSet, Sample, Name};
+use namegen::{NamePart, FormattingRule, SampleSet, Name};
 use std::fs::File;
 use std::io::Read;
 use time::PreciseTime;
@@ -12,38 +12,16 @@ fn main() {
     let mut data = String::with_capacity(2048);
     file.read_to_string(&mut data).expect("File could not be read.");
 
-    // Parse sample file.
-    let mut new_set = true;
-    let mut cutoff = 0;
-    let mut sets: Vec<SampleSet> = Vec::new();
-    for line in data.lines() {
-        if line.len() < 2 {
-            new_set = true;
-            continue;
-        }
-
-        if line == "***" {
-            cutoff = sets.len();
-            continue;
-        }
-
-        if new_set {
-            let labels: Vec<&str> = line.split(' ').filter(|t| t.len() > 0).collect();
-
-            sets.push(SampleSet::with_labels(&labels));
-            new_set = false;
-        } else {
-            sets.last_mut().unwrap().add_sample(
-                Sample::Tokens(line.split(' ').filter(|t| t.len() > 0).map(|t| t.to_owned()).collect()),
-            );
-        }
-    }
-    for set in sets[..cutoff].iter() {
+    // Parse sample file. The first names and last names are separated by a `***` line.
+    let (first_data, last_data) = data.split_at(data.find("\n***\n").expect("No *** separator in file."));
+    let first_sets = SampleSet::parse_blocks(first_data).unwrap();
+    let last_sets = SampleSet::parse_blocks(&last_data[5..]).unwrap();
+    for set in first_sets.iter() {
         if let Err(e) = part1.learn(set) {
             eprintln!("{:?}: {}", set.labels(), e);
         }
     }
-    for set in sets[cutoff..].iter() {
+    for set in last_sets.iter() {
         if let Err(e) = part2.learn(set) {
             eprintln!("{:?}: {}", set.labels(), e);
         }
@@ -53,9 +31,9 @@ fn main() {
     let mut name = Name::new();
     name.add_part(part1);
     name.add_part(part2);
-    name.add_format("first_name", "{first}");
-    name.add_format("last_name", "{last}");
-    name.add_format("full_name", "{first} {last}");
+    name.add_format("first_name", "{first}").unwrap();
+    name.add_format("last_name", "{last}").unwrap();
+    name.add_format("full_name", "{first} {last}").unwrap();
 
     // Show the structure
     #[cfg(feature = "serde")]
@@ -85,7 +63,7 @@ fn main() {
     for (i, result) in name.generate( "full_name").unwrap().enumerate().take(72) {
         print!("{result:<width$} ", result = result, width = 19);
         if i > 0 && (i % 4 == 3) {
-            print!("\n")
+            println!()
         }
     }
 
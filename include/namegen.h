@@ -0,0 +1,77 @@
+/*
+ * C interface to namegen, built with the `capi` feature. Link against the
+ * cdylib (libnamegen.so, namegen.dll or libnamegen.dylib).
+ *
+ * All strings are UTF-8. Functions that fail return null or NAMEGEN_ERROR,
+ * and namegen_last_error() tells why.
+ */
+
+#ifndef NAMEGEN_H
+#define NAMEGEN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Return values of namegen_iter_next. */
+#define NAMEGEN_OK 0
+#define NAMEGEN_DONE 1
+#define NAMEGEN_ERROR -1
+#define NAMEGEN_BUFFER_TOO_SMALL -2
+
+/* Flags for namegen_iter_new. */
+#define NAMEGEN_PART 1            /* Generate from a part instead of a format. */
+#define NAMEGEN_SEEDED 2          /* Use the seed instead of a random one. */
+#define NAMEGEN_UNIQUE 4          /* Never generate the same name twice. */
+#define NAMEGEN_EXCLUDE_SAMPLES 8 /* Reject names where a part copied a sample. */
+
+/* A model. */
+typedef struct NamegenName NamegenName;
+
+/* A stream of generated names. It keeps its model alive. */
+typedef struct NamegenIter NamegenIter;
+
+/*
+ * Get the message of the last error on this thread, or NULL if there has
+ * been none. The string is owned by the library, and it stays valid until
+ * the next error on the same thread.
+ */
+const char *namegen_last_error(void);
+
+/* Load a model saved as JSON, e.g. by the namegen tool. NULL on error. */
+NamegenName *namegen_name_from_json(const char *json);
+
+/* Like namegen_name_from_json, but the data doesn't need a terminator. */
+NamegenName *namegen_name_from_bytes(const uint8_t *data, size_t len);
+
+/* Free a model. Iterators made from it can still be used. NULL is ignored. */
+void namegen_name_free(NamegenName *name);
+
+/*
+ * Start generating names from the format, or from the part with
+ * NAMEGEN_PART. The seed is only used with NAMEGEN_SEEDED. NULL on error.
+ */
+NamegenIter *namegen_iter_new(const NamegenName *name, const char *format_name, uint32_t flags, uint64_t seed);
+
+/*
+ * Write the next name to buf with a null terminator. The length in bytes,
+ * without the terminator, is written to out_len unless it's NULL.
+ *
+ * If buf is too small, NAMEGEN_BUFFER_TOO_SMALL is returned and the name is
+ * kept for the next call, so it can be retried with a buffer of out_len + 1
+ * bytes. NAMEGEN_DONE means that no more names can be generated, e.g. with
+ * NAMEGEN_UNIQUE.
+ */
+int namegen_iter_next(NamegenIter *iter, char *buf, size_t buf_len, size_t *out_len);
+
+/* Free an iterator. NULL is ignored. */
+void namegen_iter_free(NamegenIter *iter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NAMEGEN_H */
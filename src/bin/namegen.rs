@@ -0,0 +1,412 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+use namegen::{Name, NamePart, FormattingRule, NormalizationRule, Phonotactics, PhonotacticRule, SampleSet, Syllable, Pattern};
+
+const USAGE: &str = "Usage: namegen <command> [arguments]
+
+Commands:
+  new <model>
+      Create an empty model file.
+  compile <definition> <model>
+      Build and train a model from a definition file. Sample files are relative to it.
+  add-part <model> <part> <markov|cfgrammar|wordlist|syllable|pattern> [options]
+      Add a part to the model.
+        --tokens <a,b,..>   Tokens (markov) or subtokens (cfgrammar) to treat as one letter.
+        --order <n>         Number of preceding tokens that decide the next one (markov, default: 2).
+        --creativity <c>    How often to fall back on a shorter context, from 0 to 1 (markov).
+        --lrs --lrm --lre   Length restrict start, middle and end (markov).
+        --rtf               Restrict token frequency (markov).
+        --rlf --ral         Restrict letter frequency and adjacent letters (cfgrammar).
+        --vowels <abc>      Letters to split learned samples into syllables at (syllable).
+        --pattern <p>       Pattern to generate names from, e.g. \"<B><V>'<C>\" (pattern).
+        --rule <rule>       Formatting rule, can be repeated: capitalize-first, capitalize-default,
+                            capitalize-after:<c>, remove:<c> or replace:<from><to>.
+        --normalize <a,b>   Normalize samples and scored names: nfc, nfd, lowercase or
+                            strip-diacritics.
+        --class <name:abc>  Character class for the phonotactic rules, can be repeated.
+        --phonotactic <r>   Phonotactic rule, can be repeated: max-run:<class>:<n>,
+                            not-start:<class>, not-end:<class> or require:<class>.
+  add-format <model> <format> <format string> [--lenient]
+      Add a format to the model, e.g. \"{first} {last}\". Unknown parts and formats are errors
+      unless --lenient is given. Use {{ and }} for literal braces.
+  learn <model> <part> <sample file> [--words|--blocks]
+      Learn samples from a word list (default) or block file.
+  unlearn <model> <part> <sample file> [--words|--blocks]
+      Forget samples that were learned before.
+  validate <model>
+      Check the model for errors.
+  outputs <model> <part> [--count]
+      List every name a cfgrammar or wordlist part can generate, or just count them.
+  dump <model>
+      Print the model as indented JSON.
+  generate <model> [options]
+      Generate names.
+        --format <format>   Format to generate. Defaults to the first one.
+        --part <part>       Generate a single part instead of a format.
+        -n <count>          Number of names to generate (default: 10).
+        --seed <seed>       Seed for reproducible output.
+        --output <type>     lines (default), json or csv.
+        --unique            Never output the same name twice.
+        --exclude-samples   Never output a learned sample verbatim.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if let Err(err) = run(&args) {
+        eprintln!("namegen: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let command = args.first().map(|s| s.as_str()).unwrap_or("help");
+    let args = if args.is_empty() { args } else { &args[1..] };
+
+    match command {
+        "new" => {
+            let path = positional(args, 0, "model")?;
+            save_name(path, &Name::new())
+        }
+        "compile" => {
+            let def_path = positional(args, 0, "definition")?;
+            let path = positional(args, 1, "model")?;
+
+            let data = fs::read_to_string(def_path).map_err(|e| format!("{}: {}", def_path, e))?;
+            let dir = Path::new(def_path).parent().unwrap_or_else(|| Path::new(""));
+            let name = Name::from_definition_with_loader(&data, |sample_path| {
+                fs::read_to_string(dir.join(sample_path)).map_err(|e| format!("{}: {}", sample_path, e))
+            }).map_err(|e| format!("{}: {}", def_path, e))?;
+
+            save_name(path, &name)
+        }
+        "add-part" => cmd_add_part(args),
+        "add-format" => {
+            let path = positional(args, 0, "model")?;
+            let format_name = positional(args, 1, "format")?;
+            let format = positional(args, 2, "format string")?;
+
+            let mut name = load_name_unchecked(path)?;
+            if name.has_format_name(format_name) {
+                return Err(format!("Format {} already exists", format_name));
+            }
+            if flag(args, "--lenient") {
+                name.add_format_lenient(format_name, format);
+            } else {
+                name.add_format(format_name, format)
+                    .map_err(|e| format!("Invalid format: {}\n{}", e.message(), e.highlight(format)))?;
+            }
+
+            save_name(path, &name)
+        }
+        "learn" => cmd_learn(args, false),
+        "unlearn" => cmd_learn(args, true),
+        "validate" => {
+            let path = positional(args, 0, "model")?;
+            load_name(path)?;
+
+            println!("{}: OK", path);
+            Ok(())
+        }
+        "outputs" => {
+            let path = positional(args, 0, "model")?;
+            let part_name = positional(args, 1, "part")?;
+            let name = load_name(path)?;
+
+            let part = name.parts().find(|p| p.name() == part_name).ok_or_else(|| format!("Part {} not found", part_name))?;
+            let outputs = part.outputs().ok_or_else(|| format!("Part {} cannot list its outputs", part_name))?;
+            if flag(args, "--count") {
+                println!("{}", outputs.count());
+            } else {
+                for output in outputs {
+                    println!("{}", output);
+                }
+            }
+
+            Ok(())
+        }
+        "dump" => {
+            let path = positional(args, 0, "model")?;
+            let name = load_name(path)?;
+
+            println!("{}", serde_json::to_string_pretty(&name).map_err(|e| e.to_string())?);
+            Ok(())
+        }
+        "generate" => cmd_generate(args),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command: {}\n\n{}", command, USAGE)),
+    }
+}
+
+fn cmd_add_part(args: &[String]) -> Result<(), String> {
+    let path = positional(args, 0, "model")?;
+    let part_name = positional(args, 1, "part")?;
+    let kind = positional(args, 2, "part type")?;
+
+    let tokens: Vec<&str> = match option(args, "--tokens")? {
+        Some(tokens) => tokens.split(',').filter(|t| !t.is_empty()).collect(),
+        None => Vec::new(),
+    };
+    let order: usize = match option(args, "--order")? {
+        Some(order) => match order.parse() {
+            Ok(order) if order > 0 => order,
+            _ => return Err(format!("Invalid order: {}", order)),
+        },
+        None => 2,
+    };
+    let creativity: Option<f64> = match option(args, "--creativity")? {
+        Some(c) => match c.parse() {
+            Ok(c) if (0.0..=1.0).contains(&c) => Some(c),
+            _ => return Err(format!("Invalid creativity: {}", c)),
+        },
+        None => None,
+    };
+    let normalization: Vec<NormalizationRule> = match option(args, "--normalize")? {
+        Some(rules) => rules.split(',').filter(|r| !r.is_empty())
+            .map(|r| NormalizationRule::parse(r).ok_or_else(|| format!("Invalid normalization rule: {}", r)))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    let mut rules = Vec::new();
+    let mut phonotactics = Phonotactics::new();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--rule" {
+            let rule = args.get(i + 1).ok_or("Missing value for --rule")?;
+            rules.push(FormattingRule::parse(rule).ok_or_else(|| format!("Invalid formatting rule: {}", rule))?);
+        } else if arg == "--class" {
+            let class = args.get(i + 1).ok_or("Missing value for --class")?;
+            let (name, chars) = class.split_once(':').ok_or_else(|| format!("Invalid class: {}", class))?;
+            phonotactics.add_class(name, chars);
+        } else if arg == "--phonotactic" {
+            let rule = args.get(i + 1).ok_or("Missing value for --phonotactic")?;
+            phonotactics.add_rule(PhonotacticRule::parse(rule).ok_or_else(|| format!("Invalid phonotactic rule: {}", rule))?);
+        }
+    }
+    phonotactics.validate().map_err(|e| e.to_string())?;
+
+    let mut part = match kind {
+        "markov" => NamePart::new_markov_with_order(
+            part_name, &rules, &tokens, order,
+            flag(args, "--lrs"), flag(args, "--lrm"), flag(args, "--lre"), flag(args, "--rtf"),
+        ),
+        "cfgrammar" => NamePart::new_cfgrammar(
+            part_name, &rules, &tokens,
+            flag(args, "--rlf"), flag(args, "--ral"),
+        ),
+        "wordlist" => NamePart::new_wordlist(part_name, &rules),
+        "syllable" => NamePart::new_syllable(part_name, &rules, match option(args, "--vowels")? {
+            Some(vowels) => Syllable::with_vowels(vowels),
+            None => Syllable::new(),
+        }),
+        "pattern" => {
+            let source = option(args, "--pattern")?.ok_or("Missing --pattern for pattern part")?;
+            let pattern = Pattern::parse(source)
+                .map_err(|e| format!("Invalid pattern: {}\n{}", e.message(), e.highlight(source)))?;
+            NamePart::new_pattern(part_name, &rules, pattern)
+        }
+        _ => return Err(format!("Unknown part type: {}", kind)),
+    };
+    if let Some(creativity) = creativity {
+        if !part.set_creativity(creativity) {
+            return Err(format!("Part type {} does not have a creativity", kind));
+        }
+    }
+    part.set_normalization(&normalization);
+    part.set_phonotactics(phonotactics);
+
+    let mut name = load_name(path)?;
+    if name.has_part_name(part_name) {
+        return Err(format!("Part {} already exists", part_name));
+    }
+    name.add_part(part);
+
+    save_name(path, &name)
+}
+
+fn cmd_learn(args: &[String], unlearn: bool) -> Result<(), String> {
+    let path = positional(args, 0, "model")?;
+    let part_name = positional(args, 1, "part")?;
+    let sample_path = positional(args, 2, "sample file")?;
+
+    let data = fs::read_to_string(sample_path).map_err(|e| format!("{}: {}", sample_path, e))?;
+    let sets = if flag(args, "--blocks") {
+        SampleSet::parse_blocks(&data)
+    } else {
+        SampleSet::parse_words(&data).map(|set| vec![set])
+    }.map_err(|e| format!("{}: {}", sample_path, e))?;
+
+    let mut name = load_name(path)?;
+    for set in sets.iter() {
+        if unlearn {
+            name.unlearn(part_name, set)
+        } else {
+            name.learn(part_name, set)
+        }.map_err(|e| e.to_string())?;
+    }
+
+    save_name(path, &name)
+}
+
+fn cmd_generate(args: &[String]) -> Result<(), String> {
+    let path = positional(args, 0, "model")?;
+    let name = load_name(path)?;
+
+    let count: usize = match option(args, "-n")? {
+        Some(n) => n.parse().map_err(|_| format!("Invalid count: {}", n))?,
+        None => 10,
+    };
+    let seed: Option<u64> = match option(args, "--seed")? {
+        Some(seed) => Some(seed.parse().map_err(|_| format!("Invalid seed: {}", seed))?),
+        None => None,
+    };
+
+    let iter = if let Some(part_name) = option(args, "--part")? {
+        match seed {
+            Some(seed) => name.generate_part_seeded(seed, part_name),
+            None => name.generate_part(part_name),
+        }.ok_or_else(|| format!("Part {} not found", part_name))?
+    } else {
+        let format_name = match option(args, "--format")? {
+            Some(format_name) => format_name,
+            None => name.first_format_name().ok_or("Model has no formats")?,
+        };
+
+        match seed {
+            Some(seed) => name.generate_seeded(seed, format_name),
+            None => name.generate(format_name),
+        }.ok_or_else(|| format!("Format {} not found", format_name))?
+    };
+    let mut iter = iter;
+    if flag(args, "--unique") {
+        iter = iter.unique();
+    }
+    if flag(args, "--exclude-samples") {
+        iter = iter.exclude_samples();
+    }
+    let results: Vec<String> = (&mut iter).take(count).collect();
+    if let Some(err) = iter.error() {
+        if results.is_empty() {
+            return Err(err.to_string());
+        }
+        eprintln!("namegen: Only {} of {} names could be generated: {}", results.len(), count, err);
+    } else if iter.is_exhausted() {
+        eprintln!("namegen: Only {} of {} names could be generated", results.len(), count);
+    }
+
+    match option(args, "--output")?.unwrap_or("lines") {
+        "lines" => {
+            for result in results.iter() {
+                println!("{}", result);
+            }
+        }
+        "json" => {
+            println!("{}", serde_json::to_string(&results).map_err(|e| e.to_string())?);
+        }
+        "csv" => {
+            println!("name");
+            for result in results.iter() {
+                println!("{}", csv_field(result));
+            }
+        }
+        output => return Err(format!("Unknown output type: {}", output)),
+    }
+
+    Ok(())
+}
+
+/// Load a model and check it, so that no command runs on a broken one.
+fn load_name(path: &str) -> Result<Name, String> {
+    let name = load_name_unchecked(path)?;
+    name.validate().map_err(|e| format!("{}: {}", path, e))?;
+
+    Ok(name)
+}
+
+/// Load a model without checking it. Only `add-format` does this, since the formats can refer to
+/// formats that haven't been added yet.
+fn load_name_unchecked(path: &str) -> Result<Name, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    serde_json::from_str(&data).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn save_name(path: &str, name: &Name) -> Result<(), String> {
+    let data = serde_json::to_string(name).map_err(|e| e.to_string())?;
+
+    fs::write(path, data).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Get the n-th argument that isn't an option or an option's value.
+fn positional<'a>(args: &'a [String], n: usize, what: &str) -> Result<&'a str, String> {
+    let mut skip_next = false;
+    let mut count = 0;
+
+    for arg in args.iter() {
+        if skip_next {
+            skip_next = false;
+            continue;
+        }
+        if arg.starts_with('-') && arg.len() > 1 {
+            skip_next = takes_value(arg);
+            continue;
+        }
+
+        if count == n {
+            return Ok(arg);
+        }
+        count += 1;
+    }
+
+    Err(format!("Missing argument: <{}>\n\n{}", what, USAGE))
+}
+
+fn option<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|a| a == name) {
+        Some(i) => match args.get(i + 1) {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("Missing value for {}", name)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn takes_value(arg: &str) -> bool {
+    matches!(arg, "--tokens" | "--vowels" | "--pattern" | "--order" | "--creativity" | "--normalize" | "--class" | "--phonotactic" | "--rule" | "--format" | "--part" | "-n" | "--seed" | "--output")
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positional() {
+        let args: Vec<String> = ["model.json", "--tokens", "th,ae", "--lre", "first", "markov"]
+            .iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(positional(&args, 0, "model"), Ok("model.json"));
+        assert_eq!(positional(&args, 1, "part"), Ok("first"));
+        assert_eq!(positional(&args, 2, "part type"), Ok("markov"));
+        assert!(positional(&args, 3, "other").is_err());
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Aeris"), "Aeris");
+        assert_eq!(csv_field("Aeris, the \"Bold\""), "\"Aeris, the \"\"Bold\"\"\"");
+    }
+}
@@ -0,0 +1,324 @@
+//! A C interface, built with the `capi` feature. The header is `include/namegen.h`, which has the
+//! documentation for the C side.
+//!
+//! A `Name` is shared between its handle and the iterators made from it, so the handle can be
+//! freed before the iterators are. Strings are UTF-8, and the error message of the last call that
+//! failed on a thread is kept until the next call that fails on it.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+
+use crate::{Name, GeneratorIter};
+
+pub const NAMEGEN_OK: c_int = 0;
+pub const NAMEGEN_DONE: c_int = 1;
+pub const NAMEGEN_ERROR: c_int = -1;
+pub const NAMEGEN_BUFFER_TOO_SMALL: c_int = -2;
+
+pub const NAMEGEN_PART: u32 = 1;
+pub const NAMEGEN_SEEDED: u32 = 2;
+pub const NAMEGEN_UNIQUE: u32 = 4;
+pub const NAMEGEN_EXCLUDE_SAMPLES: u32 = 8;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run the body, and turn errors and panics into the last error and the fallback value.
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_last_error(message);
+            fallback
+        }
+        Err(_) => {
+            set_last_error("Internal error".to_owned());
+            fallback
+        }
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is null", what));
+    }
+
+    CStr::from_ptr(s).to_str().map_err(|_| format!("{} is not valid UTF-8", what))
+}
+
+/// An opaque handle to a [`Name`].
+pub struct NamegenName {
+    name: Arc<Name>,
+}
+
+/// An opaque handle to a name generator iterator.
+pub struct NamegenIter {
+    // The iterator borrows from the name, so it must be dropped first.
+    iter: GeneratorIter<'static, SmallRng>,
+    _name: Arc<Name>,
+    pending: Option<String>,
+}
+
+/// Get the message of the last error on this thread, or null if there has been none. The string
+/// is owned by the library.
+#[no_mangle]
+pub extern "C" fn namegen_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null()))
+}
+
+/// Load a model saved as JSON, e.g. by the `namegen` tool. Returns null on error.
+///
+/// # Safety
+/// `json` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_name_from_json(json: *const c_char) -> *mut NamegenName {
+    guard(ptr::null_mut(), || {
+        let json = c_str(json, "json")?;
+        load(json.as_bytes())
+    })
+}
+
+/// Load a model saved as JSON from a buffer that doesn't need to be null-terminated. Returns null
+/// on error.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_name_from_bytes(data: *const u8, len: usize) -> *mut NamegenName {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            return Err("data is null".to_owned());
+        }
+
+        load(std::slice::from_raw_parts(data, len))
+    })
+}
+
+fn load(data: &[u8]) -> Result<*mut NamegenName, String> {
+    let name: Name = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    name.validate().map_err(|e| e.to_string())?;
+
+    Ok(Box::into_raw(Box::new(NamegenName{name: Arc::new(name)})))
+}
+
+/// Free a model. Iterators made from it can still be used.
+///
+/// # Safety
+/// `name` must be null or a handle that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_name_free(name: *mut NamegenName) {
+    if !name.is_null() {
+        drop(Box::from_raw(name));
+    }
+}
+
+/// Start generating names from a format, or from a part with `NAMEGEN_PART`. The seed is used
+/// with `NAMEGEN_SEEDED`. Returns null on error.
+///
+/// The iterator borrows the name for `'static` through `Arc::as_ptr`, which is only sound because
+/// `NamegenIter` declares `iter` before `_name`. Fields are dropped in declaration order, so the
+/// iterator is gone before the handle's reference to the name is released. Keep that order if the
+/// fields change.
+///
+/// # Safety
+/// `name` must be a valid handle, and `format_name` a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_iter_new(name: *const NamegenName, format_name: *const c_char, flags: u32, seed: u64) -> *mut NamegenIter {
+    guard(ptr::null_mut(), || {
+        let name = name.as_ref().ok_or("name is null")?.name.clone();
+        let format_name = c_str(format_name, "format_name")?;
+
+        // Safety: the name is kept alive by the Arc in the handle, and it's never mutated.
+        let borrowed: &'static Name = &*Arc::as_ptr(&name);
+        let iter = match (flags & NAMEGEN_PART != 0, flags & NAMEGEN_SEEDED != 0) {
+            (false, false) => borrowed.generate(format_name),
+            (false, true) => borrowed.generate_seeded(seed, format_name),
+            (true, false) => borrowed.generate_part(format_name),
+            (true, true) => borrowed.generate_part_seeded(seed, format_name),
+        }.ok_or_else(|| format!("{} not found", format_name))?;
+        let iter = if flags & NAMEGEN_UNIQUE != 0 { iter.unique() } else { iter };
+        let iter = if flags & NAMEGEN_EXCLUDE_SAMPLES != 0 { iter.exclude_samples() } else { iter };
+
+        Ok(Box::into_raw(Box::new(NamegenIter{iter, _name: name, pending: None})))
+    })
+}
+
+/// Generate the next name into the buffer, null-terminated. The length of the name in bytes,
+/// without the terminator, is written to `out_len` unless it's null. If the buffer is too small,
+/// `NAMEGEN_BUFFER_TOO_SMALL` is returned and the name is kept for the next call.
+///
+/// # Safety
+/// `iter` must be a valid handle, and `buf` must point to `buf_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_iter_next(iter: *mut NamegenIter, buf: *mut c_char, buf_len: usize, out_len: *mut usize) -> c_int {
+    guard(NAMEGEN_ERROR, || {
+        let iter = iter.as_mut().ok_or("iter is null")?;
+
+        let next = match iter.pending.take() {
+            Some(name) => name,
+            None => match iter.iter.try_next() {
+                Ok(Some(name)) => name,
+                Ok(None) => return Ok(NAMEGEN_DONE),
+                Err(err) => return Err(err.to_string()),
+            },
+        };
+
+        if let Some(out_len) = out_len.as_mut() {
+            *out_len = next.len();
+        }
+        if buf.is_null() || buf_len <= next.len() {
+            iter.pending = Some(next);
+            return Ok(NAMEGEN_BUFFER_TOO_SMALL);
+        }
+
+        ptr::copy_nonoverlapping(next.as_ptr(), buf as *mut u8, next.len());
+        *buf.add(next.len()) = 0;
+
+        Ok(NAMEGEN_OK)
+    })
+}
+
+/// Free an iterator.
+///
+/// # Safety
+/// `iter` must be null or a handle that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn namegen_iter_free(iter: *mut NamegenIter) {
+    if !iter.is_null() {
+        drop(Box::from_raw(iter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = include_str!("../include/namegen.h");
+
+    /// Check that the header declares every constant and function with the same values and
+    /// signatures as the bindings, and nothing else.
+    #[test]
+    fn test_header() {
+        let mut defines = Vec::new();
+        let mut prototypes = Vec::new();
+        for line in include_str!("capi.rs").lines() {
+            let line = line.trim();
+            if let Some(rest) = line.strip_prefix("pub const ") {
+                let (name, rest) = rest.split_once(':').unwrap();
+                let value = rest.split_once('=').unwrap().1.trim().trim_end_matches(';');
+                defines.push(format!("#define {} {}", name, value));
+            } else if line.starts_with("pub") && line.contains("extern \"C\" fn ") {
+                let signature = line.split("fn ").nth(1).unwrap().trim_end_matches('{').trim();
+                let (name, rest) = signature.split_once('(').unwrap();
+                let (params, ret) = rest.split_once(')').unwrap();
+                let ret = ret.trim().strip_prefix("-> ").map(c_type).unwrap_or_else(|| "void".to_owned());
+
+                let params: Vec<String> = params.split(", ").filter(|p| !p.is_empty()).map(|param| {
+                    let (param_name, param_type) = param.split_once(": ").unwrap();
+                    c_declaration(&c_type(param_type), param_name)
+                }).collect();
+                let params = if params.is_empty() { "void".to_owned() } else { params.join(", ") };
+                prototypes.push(format!("{}({});", c_declaration(&ret, name), params));
+            }
+        }
+
+        let header_defines: Vec<&str> = HEADER.lines()
+            .filter(|l| l.starts_with("#define NAMEGEN_") && !l.starts_with("#define NAMEGEN_H"))
+            .map(|l| l.split("/*").next().unwrap().trim_end())
+            .collect();
+        let header_prototypes: Vec<&str> = HEADER.lines()
+            .filter(|l| l.contains("namegen_") && l.ends_with(");"))
+            .collect();
+        assert_eq!(header_defines, defines);
+        assert_eq!(header_prototypes, prototypes);
+    }
+
+    /// Translate a type used by the bindings to C.
+    fn c_type(rust: &str) -> String {
+        if let Some(inner) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(inner));
+        }
+        if let Some(inner) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(inner));
+        }
+
+        match rust {
+            "c_char" => "char",
+            "c_int" => "int",
+            "u8" => "uint8_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "usize" => "size_t",
+            other => other,
+        }.to_owned()
+    }
+
+    fn c_declaration(c_type: &str, name: &str) -> String {
+        if c_type.ends_with('*') {
+            format!("{}{}", c_type, name)
+        } else {
+            format!("{} {}", c_type, name)
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut name = Name::new();
+        name.add_part(crate::NamePart::new_pattern("first", &[], crate::Pattern::parse("(Aeris|Baldur)").unwrap()));
+        name.add_format("full", "{first} the Bold").unwrap();
+        let json = CString::new(serde_json::to_string(&name).unwrap()).unwrap();
+
+        unsafe {
+            let name = namegen_name_from_json(json.as_ptr());
+            assert!(!name.is_null());
+
+            let format = CString::new("full").unwrap();
+            let iter = namegen_iter_new(name, format.as_ptr(), NAMEGEN_SEEDED | NAMEGEN_UNIQUE, 7);
+            namegen_name_free(name);
+
+            let mut buf = [0 as c_char; 8];
+            let mut len = 0;
+            assert_eq!(namegen_iter_next(iter, buf.as_mut_ptr(), buf.len(), &mut len), NAMEGEN_BUFFER_TOO_SMALL);
+            let mut big_buf = vec![0 as c_char; len + 1];
+            assert_eq!(namegen_iter_next(iter, big_buf.as_mut_ptr(), big_buf.len(), &mut len), NAMEGEN_OK);
+            let first = CStr::from_ptr(big_buf.as_ptr()).to_str().unwrap().to_owned();
+            assert_eq!(first.len(), len);
+            assert_eq!(namegen_iter_next(iter, big_buf.as_mut_ptr(), big_buf.len(), ptr::null_mut()), NAMEGEN_OK);
+            assert_ne!(CStr::from_ptr(big_buf.as_ptr()).to_str().unwrap(), first);
+            assert_eq!(namegen_iter_next(iter, big_buf.as_mut_ptr(), big_buf.len(), ptr::null_mut()), NAMEGEN_DONE);
+            namegen_iter_free(iter);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            let json = CString::new("{").unwrap();
+            assert!(namegen_name_from_json(json.as_ptr()).is_null());
+            assert!(!namegen_last_error().is_null());
+            assert!(namegen_name_from_bytes(ptr::null(), 0).is_null());
+            assert_eq!(CStr::from_ptr(namegen_last_error()).to_str().unwrap(), "data is null");
+
+            let data = b"{\"parts\": [], \"formats\": []}";
+            let name = namegen_name_from_bytes(data.as_ptr(), data.len());
+            assert!(!name.is_null());
+            let format = CString::new("full").unwrap();
+            assert!(namegen_iter_new(name, format.as_ptr(), 0, 0).is_null());
+            assert_eq!(CStr::from_ptr(namegen_last_error()).to_str().unwrap(), "full not found");
+            assert_eq!(namegen_iter_next(ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut()), NAMEGEN_ERROR);
+            namegen_name_free(name);
+        }
+    }
+}
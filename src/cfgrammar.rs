@@ -1,6 +1,8 @@
 use rand::{Rng};
-use crate::{LearnError, WorkingSet, Sample, SampleSet};
-use crate::core::ValidationError;
+use crate::{LearnError, WorkingSet, Sample, SampleSet, Phonotactics};
+use crate::core::{ValidationError, GenerateError, DEFAULT_MAX_ATTEMPTS};
+use crate::tokenizer::{Tokenizer, first_grapheme};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -12,25 +14,48 @@ pub struct CFGrammar {
     result_rules: Vec<ResultRule>,
     total_result_weight: u32,
     subtoken_frequencies: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tokenizer: Tokenizer,
     rlf: bool,
     ral: bool,
 }
 
 impl CFGrammar {
     pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
+        if self.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS).is_err() {
+            ws.result_str.clear();
+        }
+    }
+
+    /// Generate a name like `generate`, but return an error if nothing is learned, or if no name
+    /// is found after `max_attempts` restarts from a new result rule. This happens when the `rlf`
+    /// and `ral` constraints rule out every combination, which `can_generate` checks for.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize) -> Result<(), GenerateError> {
+        self.try_generate_with(ws, rng, max_attempts, &Phonotactics::new())
+    }
+
+    /// Generate a name like `try_generate` that follows the phonotactic rules. Each token is
+    /// checked as it's picked, so another token is tried as soon as a rule is broken.
+    pub fn try_generate_with(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
         ws.result.clear();
         ws.stack.clear();
         ws.stack_pos.clear();
 
-        if self.result_rules.len() == 0 {
-            return;
+        if self.result_rules.is_empty() || self.total_result_weight == 0 {
+            return Err(GenerateError::new("parts::CFGrammar", "Nothing has been learned."));
         }
 
         let mut result_index = 0usize;
+        let mut attempts = 0;
 
         loop {
             // Start it off if this is the first run, or all rules failed.
-            if ws.stack_pos.len() == 0 {
+            if ws.stack_pos.is_empty() {
+                if attempts == max_attempts {
+                    return Err(GenerateError::new("parts::CFGrammar", "No valid output found within the attempt budget."));
+                }
+                attempts += 1;
+
                 result_index = self.pick_result_rule(rng);
 
                 let token_index = self.result_rules[result_index].token_rules[0];
@@ -55,7 +80,7 @@ impl CFGrammar {
             // Check constraint: restrict adjacent subtoken. This is for dealing with subtokens like 'y'
             // that could be a consonant or vowel (prevents samples "lynaya" and "liyara" allowing
             // result "lyyana"; or "laya" and "lyna" allowing "lyya" if `rlf` is unset.)
-            if self.ral && ws.result.len() > 0 {
+            if self.ral && !ws.result.is_empty() {
                 let prev_token = &self.tokens[*ws.result.last().unwrap()];
                 let curr_token = &self.tokens[token_index];
 
@@ -64,35 +89,23 @@ impl CFGrammar {
                 }
             }
 
-            // Stop here if this is the end.
             ws.result.push(token_index);
-            if ws.result.len() == self.result_rules[result_index].token_rules.len() {
+            let complete = ws.result.len() == self.result_rules[result_index].token_rules.len();
+            if !self.check_phonotactics(ws, phonotactics, complete) {
+                ws.result.pop();
+                continue;
+            }
+
+            // Stop here if this is the end.
+            if complete {
                 // Combine the subtokens.
                 ws.subtokens.clear();
                 for i in ws.result.iter() {
-                    ws.subtokens.extend_from_slice(&self.tokens[*i].subtokens());
+                    ws.subtokens.extend_from_slice(self.tokens[*i].subtokens());
                 }
 
                 // Check constraint: restrict subtoken frequency.
-                let mut failed = false;
-
-                if self.rlf {
-                    for (i, subtoken_index) in ws.subtokens.iter().enumerate() {
-                        let mut count = 1;
-                        for j in (i+1)..ws.subtokens.len() {
-                            if ws.subtokens[j] == *subtoken_index {
-                                count += 1;
-                            }
-                        }
-
-                        if count > self.subtoken_frequencies[*subtoken_index] {
-                            failed = true;
-                            break;
-                        }
-                    }
-                }
-
-                if failed {
+                if !self.check_frequencies(&ws.subtokens) {
                     ws.result.pop();
                 } else {
                     ws.result_str.clear();
@@ -100,7 +113,7 @@ impl CFGrammar {
                         ws.result_str.push_str(self.subtokens[*subtoken_index].as_str());
                     }
 
-                    return;
+                    return Ok(());
                 }
             } else {
                 let token_rule_index = self.result_rules[result_index].token_rules[ws.result.len()];
@@ -111,6 +124,250 @@ impl CFGrammar {
         }
     }
 
+    /// Iterate over every distinct name the grammar can generate, respecting the `rlf` and `ral`
+    /// constraints. The order is deterministic, going through the result rules and their tokens
+    /// in the order they were learned. Keep in mind that the amount of names grows exponentially
+    /// with the amount of tokens per result rule.
+    ///
+    /// Names from result rules that can spell the same name in more than one way are kept in
+    /// memory to skip the repeats, so listing all of them costs memory in proportion to how many
+    /// there are. Names from the other rules are not stored.
+    pub fn outputs(&self) -> CFGrammarOutputs<'_> {
+        let rules: Vec<usize> = (0..self.result_rules.len()).filter(|i| self.result_rules[*i].weight > 0).collect();
+        let overlapping = self.overlapping_rules(&rules);
+
+        self.outputs_of(rules, overlapping)
+    }
+
+    fn outputs_of(&self, rules: Vec<usize>, overlapping: Vec<bool>) -> CFGrammarOutputs<'_> {
+        let mut rule_tokens: Vec<Vec<usize>> = Vec::with_capacity(self.token_rules.len());
+        for rule in self.token_rules.iter() {
+            let mut tokens: Vec<usize> = Vec::with_capacity(rule.tokens.len());
+            for token in rule.tokens.iter() {
+                if !tokens.contains(token) {
+                    tokens.push(*token);
+                }
+            }
+
+            rule_tokens.push(tokens);
+        }
+
+        CFGrammarOutputs{
+            grammar: self,
+            rule_tokens,
+            rules,
+            overlapping,
+            result_index: 0,
+            positions: Vec::with_capacity(8),
+            started: false,
+            seen: HashSet::new(),
+            subtokens: Vec::with_capacity(16),
+        }
+    }
+
+    /// Count the distinct names the grammar can generate, like [`CFGrammar::outputs`] would list
+    /// them. Result rules are counted without listing their names, unless two different
+    /// combinations of tokens can spell the same name, in which case those rules are listed to
+    /// find out how many distinct names they have. The count stops at `usize::MAX`.
+    pub fn count_outputs(&self) -> usize {
+        let rules: Vec<usize> = (0..self.result_rules.len()).filter(|i| self.result_rules[*i].weight > 0).collect();
+        let overlapping = self.overlapping_rules(&rules);
+
+        let mut count: u128 = 0;
+        for index in rules.iter().filter(|i| !overlapping[**i]) {
+            count = count.saturating_add(RuleCombinations::new(self, &self.result_rules[*index]).count());
+        }
+        let listed_rules = rules.into_iter().filter(|i| overlapping[*i]).collect();
+        let listed = self.outputs_of(listed_rules, overlapping).count();
+
+        count.saturating_add(listed as u128).min(usize::MAX as u128) as usize
+    }
+
+    /// Flag the result rules among `rules` that can spell a name that they or another one of them
+    /// can also spell with a different combination of tokens.
+    fn overlapping_rules(&self, rules: &[usize]) -> Vec<bool> {
+        let token_strs = self.token_strings();
+
+        let mut overlapping = vec![false; self.result_rules.len()];
+        for (i, a) in rules.iter().enumerate() {
+            for b in rules[i..].iter() {
+                if self.can_overlap(&token_strs, *a, *b) {
+                    overlapping[*a] = true;
+                    overlapping[*b] = true;
+                }
+            }
+        }
+
+        overlapping
+    }
+
+    /// Check if two different combinations of tokens from these result rules, which can be the
+    /// same rule, can spell the same name. This walks both rules at once, keeping track of the
+    /// text that one of them is ahead by. The constraints are not considered.
+    fn can_overlap(&self, token_strs: &[String], a: usize, b: usize) -> bool {
+        let rule_a = &self.result_rules[a].token_rules;
+        let rule_b = &self.result_rules[b].token_rules;
+
+        // Positions in both rules, the text `a` is ahead by (or `b` if the flag is set), and whether
+        // the combinations differ yet.
+        let mut stack: Vec<(usize, usize, String, bool, bool)> = vec![(0, 0, String::new(), false, a != b)];
+        let mut visited: HashSet<(usize, usize, String, bool, bool)> = HashSet::new();
+        while let Some(state) = stack.pop() {
+            if !visited.insert(state.clone()) {
+                continue;
+            }
+
+            let (i, j, ahead, b_ahead, differ) = state;
+            if ahead.is_empty() {
+                if i == rule_a.len() && j == rule_b.len() {
+                    if differ {
+                        return true;
+                    }
+                    continue;
+                }
+                if i == rule_a.len() || j == rule_b.len() {
+                    continue;
+                }
+
+                for ta in self.token_rules[rule_a[i]].tokens.iter() {
+                    for tb in self.token_rules[rule_b[j]].tokens.iter() {
+                        let (sa, sb) = (&token_strs[*ta], &token_strs[*tb]);
+                        let differ = differ || ta != tb;
+                        if let Some(rest) = sa.strip_prefix(sb.as_str()) {
+                            stack.push((i + 1, j + 1, rest.to_owned(), false, differ));
+                        } else if let Some(rest) = sb.strip_prefix(sa.as_str()) {
+                            stack.push((i + 1, j + 1, rest.to_owned(), true, differ));
+                        }
+                    }
+                }
+            } else {
+                let (rule, pos) = if b_ahead { (rule_a, i) } else { (rule_b, j) };
+                if pos == rule.len() {
+                    continue;
+                }
+
+                for token in self.token_rules[rule[pos]].tokens.iter() {
+                    let s = &token_strs[*token];
+                    let (i, j) = if b_ahead { (i + 1, j) } else { (i, j + 1) };
+                    if let Some(rest) = ahead.strip_prefix(s.as_str()) {
+                        stack.push((i, j, rest.to_owned(), b_ahead, true));
+                    } else if let Some(rest) = s.strip_prefix(ahead.as_str()) {
+                        stack.push((i, j, rest.to_owned(), !b_ahead, true));
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    fn token_strings(&self) -> Vec<String> {
+        self.tokens.iter()
+            .map(|t| t.subtokens().iter().map(|s| self.subtokens[*s].as_str()).collect())
+            .collect()
+    }
+
+    /// Get the likelihood of the grammar producing this exact string, or `None` if it cannot
+    /// produce it at all. Results that the `rlf` and `ral` constraints would reject are not
+    /// counted, but the retries they cause are not accounted for either, so the value is an
+    /// approximation.
+    pub fn score(&self, s: &str) -> Option<f64> {
+        if self.total_result_weight == 0 {
+            return None;
+        }
+
+        let token_strs = self.token_strings();
+
+        let mut p = 0.0;
+        let mut tokens: Vec<usize> = Vec::with_capacity(8);
+        for rule in self.result_rules.iter().filter(|r| r.weight > 0) {
+            let rule_p = rule.weight as f64 / self.total_result_weight as f64;
+            p += rule_p * self.score_rule(rule, &token_strs, s, &mut tokens);
+        }
+
+        if p > 0.0 {
+            Some(p)
+        } else {
+            None
+        }
+    }
+
+    fn score_rule(&self, rule: &ResultRule, token_strs: &[String], remainder: &str, tokens: &mut Vec<usize>) -> f64 {
+        if tokens.len() == rule.token_rules.len() {
+            if !remainder.is_empty() || !self.check_adjacent(tokens) {
+                return 0.0;
+            }
+
+            let subtokens: Vec<usize> = tokens.iter().flat_map(|t| self.tokens[*t].subtokens().iter().cloned()).collect();
+            return if self.check_frequencies(&subtokens) { 1.0 } else { 0.0 };
+        }
+
+        let token_rule = &self.token_rules[rule.token_rules[tokens.len()]];
+        let mut p = 0.0;
+        for (i, token) in token_rule.tokens.iter().enumerate() {
+            // Each distinct token is handled at its first occurrence.
+            if token_rule.tokens[..i].contains(token) || !remainder.starts_with(token_strs[*token].as_str()) {
+                continue;
+            }
+
+            let count = token_rule.tokens.iter().filter(|t| *t == token).count();
+            let token_p = count as f64 / token_rule.tokens.len() as f64;
+
+            tokens.push(*token);
+            p += token_p * self.score_rule(rule, token_strs, &remainder[token_strs[*token].len()..], tokens);
+            tokens.pop();
+        }
+
+        p
+    }
+
+    /// Check the `ral` constraint for a finished list of tokens.
+    fn check_adjacent(&self, tokens: &[usize]) -> bool {
+        if self.ral {
+            for pair in tokens.windows(2) {
+                if self.tokens[pair[1]].first() == self.tokens[pair[0]].last() {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Check the result so far against the phonotactic rules, using `result_str` as scratch space.
+    fn check_phonotactics(&self, ws: &mut WorkingSet, phonotactics: &Phonotactics, complete: bool) -> bool {
+        if phonotactics.is_empty() {
+            return true;
+        }
+
+        ws.result_str.clear();
+        for token_index in ws.result.iter() {
+            for subtoken_index in self.tokens[*token_index].subtokens() {
+                ws.result_str.push_str(&self.subtokens[*subtoken_index]);
+            }
+        }
+
+        if complete {
+            phonotactics.check(&ws.result_str)
+        } else {
+            phonotactics.check_prefix(&ws.result_str)
+        }
+    }
+
+    /// Check the `rlf` constraint for the subtokens of a finished result.
+    fn check_frequencies(&self, subtokens: &[usize]) -> bool {
+        if self.rlf {
+            for (i, subtoken_index) in subtokens.iter().enumerate() {
+                let count = 1 + subtokens[i+1..].iter().filter(|s| *s == subtoken_index).count();
+                if count > self.subtoken_frequencies[*subtoken_index] {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     fn pick_result_rule(&self, rng: &mut impl Rng) -> usize {
         let mut random = rng.gen_range(0, self.total_result_weight);
 
@@ -131,7 +388,7 @@ impl CFGrammar {
         for sample in sample_set.samples().iter() {
             match sample {
                 Sample::Tokens(tokens) => {
-                    if tokens.len() == 0 || (tokens_len > 0 && tokens_len != tokens.len()) {
+                    if tokens.is_empty() || (tokens_len > 0 && tokens_len != tokens.len()) {
                         return Err(LearnError::new(
                             3,
                             "Token lengths must match".to_owned(),
@@ -163,7 +420,7 @@ impl CFGrammar {
 
         // Ensure token rules
         let mut token_rule_indices: Vec<usize> = Vec::with_capacity(tokens_len);
-        if sample_set.labels().len() > 0 {
+        if !sample_set.labels().is_empty() {
             for label in sample_set.labels().iter() {
                 if label.starts_with("anon_") {
                     return Err(LearnError::new(
@@ -202,12 +459,98 @@ impl CFGrammar {
                 }
 
                 self.learn_subtoken_frequencies(&token_indices_buf);
+                self.result_rules[result_index].samples.push(token_indices_buf.clone());
             }
         }
 
         Ok(())
     }
 
+    /// Forget the samples, taking them out of the weight of their result rule and the tokens of
+    /// their token rules. Samples without labels are matched with any rule of anonymous labels,
+    /// since each `learn` call makes new ones. The letter frequency restriction is left as it
+    /// was, since it cannot be worked out without the remaining samples. The former state is
+    /// restored if one of the samples was never learned.
+    ///
+    /// Result rules keep the token combinations of their samples, so only those can be unlearned.
+    /// Rules learned before they were kept only check that each token is in its token rule.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let old_state = self.clone();
+        for sample in sample_set.samples() {
+            if let Err(err) = self.unlearn_one(sample_set.labels(), sample) {
+                *self = old_state;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unlearn_one(&mut self, labels: &[String], sample: &Sample) -> Result<(), LearnError> {
+        let tokens = match sample {
+            Sample::Tokens(tokens) => tokens,
+            _ => {
+                return Err(LearnError::new(
+                    0,
+                    "Word type sample not supported".to_owned(),
+                    Some(sample.clone()),
+                ));
+            }
+        };
+        let not_learned = || LearnError::new(5, "Sample has not been learned".to_owned(), Some(sample.clone()));
+
+        if !labels.is_empty() && labels.len() != tokens.len() {
+            return Err(LearnError::new(3, "Token lengths must match".to_owned(), Some(sample.clone())));
+        }
+        self.tokenizer.sync(&self.subtokens);
+        let token_indices: Vec<usize> = tokens.iter()
+            .map(|t| self.find_token(t))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(not_learned)?;
+
+        let result_index = self.result_rules.iter().position(|rule| {
+            rule.weight > 0 && rule.token_rules.len() == token_indices.len()
+                && rule.token_rules.iter().enumerate().all(|(i, tr)| {
+                    let token_rule = &self.token_rules[*tr];
+                    let label_matches = match labels.get(i).map(|l| l.as_str()) {
+                        Some("*") | None => token_rule.name.starts_with("anon_"),
+                        Some(label) => token_rule.name == label,
+                    };
+
+                    label_matches
+                })
+                && (rule.samples.contains(&token_indices) || (rule.samples.len() < rule.weight as usize
+                    && rule.token_rules.iter().zip(token_indices.iter()).all(|(tr, t)| self.token_rules[*tr].tokens.contains(t))))
+        }).ok_or_else(not_learned)?;
+
+        let samples = &mut self.result_rules[result_index].samples;
+        if let Some(pos) = samples.iter().position(|s| *s == token_indices) {
+            samples.remove(pos);
+        }
+        self.result_rules[result_index].weight -= 1;
+        self.total_result_weight -= 1;
+        for (i, tr) in self.result_rules[result_index].token_rules.iter().enumerate() {
+            let rule_tokens = &mut self.token_rules[*tr].tokens;
+            let pos = rule_tokens.iter().position(|t| *t == token_indices[i]).unwrap();
+            rule_tokens.remove(pos);
+        }
+
+        Ok(())
+    }
+
+    fn find_token(&self, token_str: &str) -> Option<usize> {
+        let mut subtokens = Vec::with_capacity(16);
+        let mut subtoken_pos = 0;
+        while subtoken_pos < token_str.len() {
+            let (subtoken_index, subtoken_len) = self.tokenizer.longest_match(&token_str[subtoken_pos..])?;
+
+            subtokens.push(subtoken_index);
+            subtoken_pos += subtoken_len;
+        }
+
+        self.tokens.iter().position(|Token(subtokens2)| subtokens2 == &subtokens)
+    }
+
     fn learn_subtoken_frequencies(&mut self, token_indices: &[usize]) {
         let mut subtoken_indices: Vec<usize> = Vec::with_capacity(token_indices.len() * 4);
         for i in token_indices {
@@ -223,17 +566,19 @@ impl CFGrammar {
         }
     }
 
+    /// Find the longest subtoken at the start of the string, or add its first grapheme cluster as
+    /// a new one. This returns the subtoken's index and its length in bytes.
     fn ensure_subtokens(&mut self, token_str: &str) -> (usize, usize) {
-        for (i, subtoken) in self.subtokens.iter().enumerate() {
-            if token_str.starts_with(subtoken.as_str()) {
-                return (i, subtoken.len());
-            }
+        self.tokenizer.sync(&self.subtokens);
+        if let Some(found) = self.tokenizer.longest_match(token_str) {
+            return found;
         }
 
-        self.subtokens.push(token_str.chars().take(1).collect());
+        let first = first_grapheme(token_str);
+        self.subtokens.push(first.to_owned());
         self.subtoken_frequencies.push(1);
 
-        (self.subtokens.len() - 1, 1)
+        (self.subtokens.len() - 1, first.len())
     }
 
     fn ensure_token(&mut self, token_str: &str) -> usize {
@@ -243,7 +588,7 @@ impl CFGrammar {
             let (subtoken_index, subtoken_len) = self.ensure_subtokens(&token_str[subtoken_pos..]);
 
             subtokens.push(subtoken_index);
-            subtoken_pos += token_str[subtoken_pos..].chars().take(subtoken_len).map(|c| c.len_utf8()).sum::<usize>();
+            subtoken_pos += subtoken_len;
         }
 
         for (i, Token(subtokens2)) in self.tokens.iter().enumerate() {
@@ -268,7 +613,7 @@ impl CFGrammar {
 
     fn ensure_token_rule(&mut self, name: &str) -> usize {
         for (i, rule) in self.token_rules.iter().enumerate() {
-            if &rule.name == name {
+            if rule.name == name {
                 return i;
             }
         }
@@ -291,6 +636,7 @@ impl CFGrammar {
         self.result_rules.push(ResultRule{
             token_rules: token_indices.to_vec(),
             weight: 0,
+            samples: Vec::new(),
         });
 
         self.result_rules.len() - 1
@@ -303,6 +649,10 @@ impl CFGrammar {
                     return Err(ValidationError::new("parts::CFGrammar", "result_rule references invalid token"));
                 }
             }
+
+            if rule.samples.len() > rule.weight as usize {
+                return Err(ValidationError::new("parts::CFGrammar", "result_rule has more samples than weight."));
+            }
         }
 
         let total_weight: u32 = self.result_rules.iter().map(|r| r.weight).sum();
@@ -330,9 +680,23 @@ impl CFGrammar {
             return Err(ValidationError::new("parts::CFGrammar", "subtoken frequencies does not match subtokens."));
         }
 
+        if self.total_result_weight > 0 && !self.can_generate() {
+            return Err(ValidationError::new("parts::CFGrammar", "constraints rule out every output."));
+        }
+
         Ok(())
     }
 
+    /// Check whether any combination of tokens satisfies the constraints, which `try_generate`
+    /// needs to succeed. The tokens are tried position by position, and the states that lead
+    /// nowhere are remembered, so a grammar that has no outputs is not gone through one
+    /// combination at a time.
+    pub fn can_generate(&self) -> bool {
+        self.result_rules.iter()
+            .filter(|rule| rule.weight > 0)
+            .any(|rule| RuleCombinations::new(self, rule).any())
+    }
+
     pub fn new<S: AsRef<str>>(initial_subtokens: &[S], rlf: bool, ral: bool) -> CFGrammar {
         CFGrammar{
             tokens: Vec::new(),
@@ -341,18 +705,211 @@ impl CFGrammar {
             token_rules: Vec::new(),
             total_result_weight: 0,
             subtoken_frequencies: vec![1; initial_subtokens.len()],
+            tokenizer: Tokenizer::new(initial_subtokens),
 
             rlf, ral,
         }
     }
 }
 
+/// Iterator over the distinct outputs of a [`CFGrammar`], created by [`CFGrammar::outputs`].
+pub struct CFGrammarOutputs<'a> {
+    grammar: &'a CFGrammar,
+    rule_tokens: Vec<Vec<usize>>,
+    rules: Vec<usize>,
+    overlapping: Vec<bool>,
+    result_index: usize,
+    positions: Vec<usize>,
+    started: bool,
+    seen: HashSet<String>,
+    subtokens: Vec<usize>,
+}
+
+impl<'a> CFGrammarOutputs<'a> {
+    /// Move on to the next combination of tokens in the current result rule.
+    fn advance(&mut self) -> bool {
+        let result_rule = &self.grammar.result_rules[self.rules[self.result_index]];
+        let token_rules = &result_rule.token_rules;
+
+        if !self.started {
+            self.started = true;
+            self.positions.clear();
+            self.positions.resize(token_rules.len(), 0);
+
+            return result_rule.weight > 0
+                && token_rules.iter().all(|tr| !self.rule_tokens[*tr].is_empty());
+        }
+
+        for i in (0..self.positions.len()).rev() {
+            self.positions[i] += 1;
+            if self.positions[i] < self.rule_tokens[token_rules[i]].len() {
+                return true;
+            }
+
+            self.positions[i] = 0;
+        }
+
+        false
+    }
+}
+
+impl<'a> Iterator for CFGrammarOutputs<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let grammar = self.grammar;
+        let mut tokens: Vec<usize> = Vec::with_capacity(8);
+
+        while self.result_index < self.rules.len() {
+            if !self.advance() {
+                self.result_index += 1;
+                self.started = false;
+                continue;
+            }
+
+            let token_rules = &grammar.result_rules[self.rules[self.result_index]].token_rules;
+            tokens.clear();
+            tokens.extend(self.positions.iter().zip(token_rules.iter()).map(|(p, tr)| self.rule_tokens[*tr][*p]));
+
+            if !grammar.check_adjacent(&tokens) {
+                continue;
+            }
+
+            self.subtokens.clear();
+            for i in tokens.iter() {
+                self.subtokens.extend_from_slice(grammar.tokens[*i].subtokens());
+            }
+            if !grammar.check_frequencies(&self.subtokens) {
+                continue;
+            }
+
+            let result: String = self.subtokens.iter().map(|s| grammar.subtokens[*s].as_str()).collect();
+            if !self.overlapping[self.rules[self.result_index]] || self.seen.insert(result.clone()) {
+                return Some(result);
+            }
+        }
+
+        None
+    }
+}
+
+/// The combinations of tokens a result rule allows under the `rlf` and `ral` constraints. These
+/// only depend on the last subtoken so far, and on how often the subtokens that could break their
+/// frequency limit have been used, so combinations that share those are handled once.
+struct RuleCombinations<'a> {
+    grammar: &'a CFGrammar,
+    positions: Vec<Vec<usize>>,
+    limited: Vec<usize>,
+    counts: HashMap<(usize, Option<usize>, Vec<usize>), u128>,
+    dead_ends: HashSet<(usize, Option<usize>, Vec<usize>)>,
+}
+
+impl<'a> RuleCombinations<'a> {
+    fn new(grammar: &'a CFGrammar, rule: &ResultRule) -> RuleCombinations<'a> {
+        let positions: Vec<Vec<usize>> = rule.token_rules.iter().map(|tr| {
+            let mut tokens = grammar.token_rules[*tr].tokens.clone();
+            tokens.sort_unstable();
+            tokens.dedup();
+            tokens
+        }).collect();
+
+        let mut limited = Vec::new();
+        if grammar.rlf {
+            for (subtoken, limit) in grammar.subtoken_frequencies.iter().enumerate() {
+                let most: usize = positions.iter()
+                    .map(|tokens| tokens.iter().map(|t| grammar.tokens[*t].subtokens().iter().filter(|s| **s == subtoken).count()).max().unwrap_or(0))
+                    .sum();
+                if most > *limit {
+                    limited.push(subtoken);
+                }
+            }
+        }
+
+        RuleCombinations{grammar, positions, limited, counts: HashMap::new(), dead_ends: HashSet::new()}
+    }
+
+    /// Check if there is any combination.
+    fn any(&mut self) -> bool {
+        let used = vec![0; self.limited.len()];
+        self.any_from(0, None, &used)
+    }
+
+    fn any_from(&mut self, pos: usize, last: Option<usize>, used: &[usize]) -> bool {
+        if pos == self.positions.len() {
+            return true;
+        }
+        let key = (pos, last, used.to_vec());
+        if self.dead_ends.contains(&key) {
+            return false;
+        }
+
+        for i in 0..self.positions[pos].len() {
+            if let Some((last, used)) = self.step(last, used, self.positions[pos][i]) {
+                if self.any_from(pos + 1, last, &used) {
+                    return true;
+                }
+            }
+        }
+
+        self.dead_ends.insert(key);
+        false
+    }
+
+    /// Count the combinations, saturating at `u128::MAX`.
+    fn count(&mut self) -> u128 {
+        let used = vec![0; self.limited.len()];
+        self.count_from(0, None, &used)
+    }
+
+    fn count_from(&mut self, pos: usize, last: Option<usize>, used: &[usize]) -> u128 {
+        if pos == self.positions.len() {
+            return 1;
+        }
+        let key = (pos, last, used.to_vec());
+        if let Some(count) = self.counts.get(&key) {
+            return *count;
+        }
+
+        let mut count: u128 = 0;
+        for i in 0..self.positions[pos].len() {
+            if let Some((last, used)) = self.step(last, used, self.positions[pos][i]) {
+                count = count.saturating_add(self.count_from(pos + 1, last, &used));
+            }
+        }
+
+        self.counts.insert(key, count);
+        count
+    }
+
+    /// Add a token after `last`, or return `None` if that breaks a constraint.
+    fn step(&self, last: Option<usize>, used: &[usize], token_index: usize) -> Option<(Option<usize>, Vec<usize>)> {
+        let token = &self.grammar.tokens[token_index];
+        if last == Some(token.first()) {
+            return None;
+        }
+
+        let mut used = used.to_vec();
+        for (i, subtoken) in self.limited.iter().enumerate() {
+            used[i] += token.subtokens().iter().filter(|s| *s == subtoken).count();
+            if used[i] > self.grammar.subtoken_frequencies[*subtoken] {
+                return None;
+            }
+        }
+
+        Some((if self.grammar.ral { Some(token.last()) } else { None }, used))
+    }
+}
+
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 struct ResultRule {
     token_rules: Vec<usize>,
     weight: u32,
+    /// The tokens of each learned sample, for `unlearn`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    samples: Vec<Vec<usize>>,
 }
 
 #[derive(Clone)]
@@ -384,4 +941,204 @@ impl Token {
     fn new(subtoken_indices: &[usize]) -> Token {
         Token(subtoken_indices.to_vec())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    #[test]
+    fn test_outputs() {
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, false);
+        grammar.learn(&sample_set(&["SV", "*", "END"], &["e le na", "i ly na", "e ly na"])).unwrap();
+        grammar.learn(&sample_set(&["SV", "END"], &["a na"])).unwrap();
+
+        let outputs: Vec<String> = grammar.outputs().collect();
+        assert_eq!(outputs, vec!["elena", "elyna", "ilena", "ilyna", "alena", "alyna", "ena", "ina", "ana"]);
+        assert_eq!(grammar.count_outputs(), 9);
+    }
+
+    #[test]
+    fn test_outputs_distinct() {
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, false);
+        grammar.learn(&sample_set(&["*", "*"], &["a na", "an a"])).unwrap();
+
+        let outputs: Vec<String> = grammar.outputs().collect();
+        assert_eq!(outputs, vec!["ana", "aa", "anna"]);
+        assert_eq!(grammar.count_outputs(), 3);
+    }
+
+    #[test]
+    fn test_count_outputs() {
+        let grammars = [
+            (false, false, vec![(vec!["*", "*"], vec!["a na", "e ly"]), (vec!["*"], vec!["ana", "ina"])]),
+            (false, false, vec![(vec!["SV", "END"], vec!["a na", "e ly"]), (vec!["SV", "*", "END"], vec!["a n a", "e l y"])]),
+            (true, true, vec![(vec!["*", "*", "*"], vec!["la ya na", "ly na ra", "la na va"])]),
+            (true, false, vec![(vec!["*", "*"], vec!["ta ta", "ka ta", "ta ka"]), (vec!["*"], vec!["kata"])]),
+        ];
+        for (rlf, ral, sets) in grammars.iter() {
+            let mut grammar = CFGrammar::new(&[] as &[&str], *rlf, *ral);
+            for (labels, samples) in sets.iter() {
+                grammar.learn(&sample_set(labels, samples)).unwrap();
+            }
+
+            let outputs: Vec<String> = grammar.outputs().collect();
+            assert_eq!(outputs.iter().collect::<HashSet<_>>().len(), outputs.len());
+            assert_eq!(grammar.count_outputs(), outputs.len());
+        }
+
+        // Twelve positions of ten letters each would take ages to list.
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, false);
+        let samples: Vec<String> = "abcdefghij".chars().map(|c| vec![c.to_string(); 12].join(" ")).collect();
+        let samples: Vec<&str> = samples.iter().map(|s| s.as_str()).collect();
+        grammar.learn(&sample_set(&[], &samples)).unwrap();
+        assert_eq!(grammar.count_outputs(), 1_000_000_000_000);
+    }
+
+    #[test]
+    fn test_outputs_constraints() {
+        let mut grammar = CFGrammar::new(&[] as &[&str], true, true);
+        grammar.learn(&sample_set(&["*", "*", "*"], &["la ya na", "ly na ra", "la na va"])).unwrap();
+
+        for output in grammar.outputs() {
+            assert!(!output.contains("yy"), "{}", output);
+            assert!(output.matches('n').count() <= 1, "{}", output);
+            assert!(output.matches('a').count() <= 3, "{}", output);
+        }
+        assert!(grammar.outputs().any(|o| o == "lanara"));
+        assert!(!grammar.outputs().any(|o| o == "lynana"));
+        assert!(!grammar.outputs().any(|o| o == "lyyana"));
+    }
+
+    #[test]
+    fn test_try_generate() {
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(7);
+
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, true);
+        assert!(grammar.try_generate(&mut ws, &mut rng, 10).is_err());
+
+        // The only combination has the same letter on both sides of the seam.
+        grammar.learn(&sample_set(&["*", "*"], &["la ay"])).unwrap();
+        assert!(!grammar.can_generate());
+        assert!(grammar.validate().is_err());
+        assert!(grammar.try_generate(&mut ws, &mut rng, 10).is_err());
+        ws.result_str.push_str("stale");
+        grammar.generate(&mut ws, &mut rng);
+        assert_eq!(ws.get_result(), "");
+
+        grammar.learn(&sample_set(&["*", "*"], &["le na"])).unwrap();
+        assert!(grammar.can_generate());
+        grammar.validate().unwrap();
+        grammar.try_generate(&mut ws, &mut rng, 10).unwrap();
+        assert!(["lena", "lana", "leay"].contains(&ws.get_result()), "{}", ws.get_result());
+
+        // Every seam has the same letter on both sides, which takes too long to find by listing.
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, true);
+        let samples: Vec<String> = "abcdefghij".chars().map(|c| vec![format!("x{}x", c); 12].join(" ")).collect();
+        let samples: Vec<&str> = samples.iter().map(|s| s.as_str()).collect();
+        grammar.learn(&sample_set(&[], &samples)).unwrap();
+        assert!(!grammar.can_generate());
+        assert!(grammar.validate().is_err());
+        assert_eq!(grammar.count_outputs(), 0);
+    }
+
+    #[test]
+    fn test_unlearn() {
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, false);
+        grammar.learn(&sample_set(&["SV", "END"], &["e na", "i ly", "a na"])).unwrap();
+        grammar.learn(&sample_set(&["*", "*"], &["ma ra"])).unwrap();
+        assert!(grammar.unlearn(&sample_set(&["SV", "END"], &["e ly"])).is_err());
+
+        grammar.unlearn(&sample_set(&["SV", "END"], &["i ly"])).unwrap();
+        grammar.unlearn(&sample_set(&["*", "*"], &["ma ra"])).unwrap();
+        grammar.validate().unwrap();
+        assert_eq!(grammar.outputs().collect::<Vec<_>>(), vec!["ena", "ana"]);
+
+        assert!(grammar.unlearn(&sample_set(&["SV", "END"], &["e na", "i ly"])).is_err());
+        assert!(grammar.unlearn(&sample_set(&["SV", "END"], &["e xa"])).is_err());
+        assert!(grammar.unlearn(&sample_set(&["SV", "X"], &["e na"])).is_err());
+        assert_eq!(grammar.count_outputs(), 2);
+
+        grammar.unlearn(&sample_set(&["SV", "END"], &["e na", "a na"])).unwrap();
+        grammar.validate().unwrap();
+        assert_eq!(grammar.count_outputs(), 0);
+        assert!(grammar.try_generate(&mut WorkingSet::new(), &mut SmallRng::seed_from_u64(1), 10).is_err());
+
+        // Only the learned combinations can be unlearned, even though each token is known.
+        grammar.learn(&sample_set(&["*", "*"], &["ma na", "re la"])).unwrap();
+        assert!(grammar.unlearn(&sample_set(&[], &["ma la"])).is_err());
+        assert_eq!(grammar.total_result_weight, 2);
+
+        // Rules from before the combinations were kept only check the tokens.
+        for rule in grammar.result_rules.iter_mut() {
+            rule.samples.clear();
+        }
+        grammar.unlearn(&sample_set(&[], &["ma la"])).unwrap();
+        grammar.validate().unwrap();
+    }
+
+    #[test]
+    fn test_phonotactics() {
+        use crate::PhonotacticRule;
+
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, false);
+        grammar.learn(&sample_set(&["*", "*", "*"], &["sa rn a", "e lk in", "o st or"])).unwrap();
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_rule(PhonotacticRule::parse("max-run:consonant:2").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("not-end:vowel").unwrap());
+
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut seen = HashSet::new();
+        for _ in 0..200 {
+            grammar.try_generate_with(&mut ws, &mut rng, 1024, &phonotactics).unwrap();
+            assert!(phonotactics.check(&ws.result_str), "{}", ws.result_str);
+            seen.insert(ws.result_str.clone());
+        }
+        let expected: HashSet<String> = grammar.outputs().filter(|o| phonotactics.check(o)).collect();
+        assert_eq!(seen, expected);
+
+        phonotactics.add_rule(PhonotacticRule::parse("require:sibilant").unwrap());
+        assert!(grammar.try_generate_with(&mut ws, &mut rng, 100, &phonotactics).is_err());
+    }
+
+    #[test]
+    fn test_subtokens() {
+        let mut grammar = CFGrammar::new(&["ø", "øy", "t"], false, false);
+        grammar.learn(&sample_set(&["*", "*"], &["øyt ø", "tø ta"])).unwrap();
+
+        assert_eq!(grammar.subtokens, vec!["ø", "øy", "t", "a"]);
+        let tokens: Vec<Vec<usize>> = grammar.tokens.iter().map(|t| t.subtokens().to_vec()).collect();
+        assert_eq!(tokens, vec![vec![1, 2], vec![0], vec![2, 0], vec![2, 3]]);
+
+        grammar.unlearn(&sample_set(&["*", "*"], &["øyt ø"])).unwrap();
+        assert_eq!(grammar.outputs().collect::<Vec<_>>(), vec!["tøta"]);
+    }
+
+    #[test]
+    fn test_score() {
+        let mut grammar = CFGrammar::new(&[] as &[&str], false, true);
+        grammar.learn(&sample_set(&["*", "*"], &["a na", "e na", "e ly"])).unwrap();
+        grammar.learn(&sample_set(&["*"], &["ana"])).unwrap();
+
+        // 3/4 for the first result rule, times 1/3 for "a" and 2/3 for "na", plus 1/4 for the second.
+        assert!((grammar.score("ana").unwrap() - (0.75 * (1.0 / 3.0) * (2.0 / 3.0) + 0.25)).abs() < 1e-9);
+        assert!((grammar.score("ely").unwrap() - (0.75 * (2.0 / 3.0) * (1.0 / 3.0))).abs() < 1e-9);
+        assert!(grammar.score("ena").is_some());
+        assert!(grammar.score("aly").is_some());
+        assert_eq!(grammar.score("anaa"), None);
+        assert_eq!(grammar.score("na"), None);
+    }
+
+    fn sample_set(labels: &[&str], samples: &[&str]) -> SampleSet {
+        let mut sample_set = SampleSet::with_labels(labels);
+        for sample in samples.iter() {
+            sample_set.add_sample(Sample::Tokens(sample.split(' ').map(|s| s.to_owned()).collect()));
+        }
+
+        sample_set
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{Name, NamePart};
+use std::cmp::Ordering;
+
+enum Source<'a> {
+    Part(&'a NamePart),
+    Name(&'a Name),
+    Format(&'a Name, String),
+}
+
+impl<'a> Source<'a> {
+    fn score(&self, input: &str) -> Option<f64> {
+        match self {
+            Source::Part(part) => part.score(input),
+            Source::Name(name) => name.score(input),
+            Source::Format(name, format_name) => name.score_format(format_name, input),
+        }
+    }
+}
+
+/// A label that matched the input of [`Classifier::classify`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    label: String,
+    score: f64,
+    confidence: f64,
+}
+
+impl Match {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The probability that the label's best source generates the input.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    /// The share of the scores of all matches that belongs to this one, between 0 and 1. This
+    /// assumes that every label is equally likely up front.
+    pub fn confidence(&self) -> f64 {
+        self.confidence
+    }
+}
+
+/// A Classifier ranks labeled name parts and names by how likely they are to have generated an
+/// input, e.g. to tag imported names with a culture, or to check that a name fits a faction.
+///
+/// Several sources can share a label, in which case the best scoring one counts. This lets a
+/// label cover a culture's first and last names without them competing against each other.
+pub struct Classifier<'a> {
+    sources: Vec<(String, Source<'a>)>,
+}
+
+impl<'a> Classifier<'a> {
+    /// Add a part, which is scored with [`NamePart::score`].
+    pub fn add_part(&mut self, label: &str, part: &'a NamePart) {
+        self.sources.push((label.to_owned(), Source::Part(part)));
+    }
+
+    /// Add a name, which is scored with [`Name::score`] against its best fitting format.
+    pub fn add_name(&mut self, label: &str, name: &'a Name) {
+        self.sources.push((label.to_owned(), Source::Name(name)));
+    }
+
+    /// Add a single format of a name, which is scored with [`Name::score_format`].
+    pub fn add_format(&mut self, label: &str, name: &'a Name, format_name: &str) {
+        self.sources.push((label.to_owned(), Source::Format(name, format_name.to_owned())));
+    }
+
+    /// Get every label that can generate the input, best match first. Labels that cannot
+    /// generate it at all are left out, so the list is empty if nothing matches.
+    pub fn classify(&self, input: &str) -> Vec<Match> {
+        let mut matches: Vec<Match> = Vec::with_capacity(self.sources.len());
+
+        for (label, source) in self.sources.iter() {
+            let score = match source.score(input) {
+                Some(score) => score,
+                None => continue,
+            };
+
+            match matches.iter_mut().find(|m| &m.label == label) {
+                Some(m) => m.score = m.score.max(score),
+                None => matches.push(Match{
+                    label: label.clone(),
+                    score,
+                    confidence: 0.0,
+                }),
+            }
+        }
+
+        let total: f64 = matches.iter().map(|m| m.score).sum();
+        for m in matches.iter_mut() {
+            m.confidence = m.score / total;
+        }
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+        matches
+    }
+
+    /// Get the best match for the input, if any.
+    pub fn best_match(&self, input: &str) -> Option<Match> {
+        self.classify(input).into_iter().next()
+    }
+
+    pub fn new() -> Classifier<'a> {
+        Classifier{
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Default for Classifier<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SampleSet, Sample, FormattingRule};
+
+    #[test]
+    fn test_classify() {
+        let nordic = markov("first", &["bjorn", "sigrid", "ingrid", "astrid", "gunnar", "sigurd", "ragnar", "halvard"]);
+        let latin = markov("first", &["marcus", "julius", "lucius", "aurelia", "cornelia", "octavia", "tiberius", "livia"]);
+        let mut nordic_name = Name::new();
+        nordic_name.add_part(nordic);
+        nordic_name.add_format("first", "{first}").unwrap();
+
+        let mut classifier = Classifier::new();
+        classifier.add_name("nordic", &nordic_name);
+        classifier.add_part("latin", &latin);
+
+        let matches = classifier.classify("Ingrid");
+        assert_eq!(matches[0].label(), "nordic");
+        assert!(matches.iter().all(|m| m.label() != "latin" || m.score() < matches[0].score()));
+
+        let best = classifier.best_match("Julia").unwrap();
+        assert_eq!(best.label(), "latin");
+
+        let total: f64 = classifier.classify("Astrid").iter().map(|m| m.confidence()).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        assert!(classifier.classify("Xyzzy").is_empty());
+        assert_eq!(classifier.best_match("Xyzzy"), None);
+    }
+
+    #[test]
+    fn test_shared_label() {
+        let first = markov("first", &["aeris", "celia", "dorian"]);
+        let last = markov("last", &["stonewall", "ironforge", "oakheart"]);
+
+        let mut classifier = Classifier::new();
+        classifier.add_part("human", &first);
+        classifier.add_part("human", &last);
+
+        let matches = classifier.classify("Ironforge");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].score(), last.score("Ironforge").unwrap());
+        assert_eq!(matches[0].confidence(), 1.0);
+    }
+
+    fn markov(name: &str, words: &[&str]) -> NamePart {
+        let mut part = NamePart::new_markov(name, &[FormattingRule::CapitalizeFirst], &[] as &[&str], false, false, false, false);
+        let mut sample_set = SampleSet::new();
+        for word in words.iter() {
+            sample_set.add_sample(Sample::Word((*word).to_owned()));
+        }
+        part.learn(&sample_set).unwrap();
+
+        part
+    }
+}
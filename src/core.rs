@@ -2,6 +2,9 @@ use std::error::Error;
 
 use crate::Sample;
 
+/// The number of restarts `generate` allows before giving up on a generator.
+pub(crate) const DEFAULT_MAX_ATTEMPTS: usize = 1024;
+
 /// A WorkingSet is a crucial part of this generator's performance. It is all local state required
 /// to generate a name and get the output without performing additional allocations per generation
 /// once the WorkingSet's underlying vectors have grown.
@@ -19,6 +22,7 @@ pub struct WorkingSet {
     pub stack: Vec<usize>,
     pub stack_pos: Vec<usize>,
     pub stack_weight: Vec<usize>,
+    pub stack_backoff: Vec<bool>,
     pub subtokens: Vec<usize>,
 }
 
@@ -39,11 +43,18 @@ impl WorkingSet {
             stack: Vec::with_capacity(128),
             stack_pos: Vec::with_capacity(16),
             stack_weight: Vec::with_capacity(16),
+            stack_backoff: Vec::with_capacity(16),
             subtokens: Vec::new(),
         }
     }
 }
 
+impl Default for WorkingSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
@@ -78,6 +89,97 @@ impl Error for LearnError {
     }
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ParseError {
+    line: usize,
+    desc: String,
+}
+
+impl ParseError {
+    /// Get the line number, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Move the error down by a number of lines. This is for when the parsed data is embedded
+    /// in a bigger document.
+    pub fn with_offset(&self, lines: usize) -> ParseError {
+        ParseError{
+            line: self.line + lines,
+            desc: self.desc.clone(),
+        }
+    }
+
+    pub fn new(line: usize, desc: String) -> ParseError {
+        ParseError{line, desc}
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ParseError at line {}: {}", self.line, self.desc)
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        &self.desc
+    }
+}
+
+/// An error in a pattern or format string, which points at the problem in it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct SyntaxError {
+    offset: usize,
+    message: String,
+}
+
+impl SyntaxError {
+    /// Get the position of the problem, counted in bytes from the start of the string.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Get the string with a line under it that points at the problem, for showing the error in
+    /// a monospace font.
+    pub fn highlight(&self, source: &str) -> String {
+        let column = source.get(..self.offset).map(|s| s.chars().count()).unwrap_or(self.offset);
+        format!("{}\n{}^", source, " ".repeat(column))
+    }
+
+    pub fn new(offset: usize, message: String) -> SyntaxError {
+        SyntaxError{offset, message}
+    }
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SyntaxError at {}: {}", self.offset, self.message)
+    }
+}
+
+impl Error for SyntaxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
@@ -113,6 +215,70 @@ impl Error for ValidationError {
     }
 
     fn description(&self) -> &str {
-        &self.message
+        self.message
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct GenerateError {
+    kind: &'static str,
+    part: Option<String>,
+    format: Option<String>,
+    message: &'static str,
+}
+
+impl GenerateError {
+    /// Get the name of the part that failed, if it was generated through a `NamePart`.
+    pub fn part(&self) -> Option<&str> {
+        self.part.as_deref()
+    }
+
+    /// Get the name of the innermost format that failed, if it was generated through a format.
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    pub fn with_part(mut self, part: &str) -> GenerateError {
+        if self.part.is_none() {
+            self.part = Some(part.to_owned());
+        }
+        self
+    }
+
+    pub fn with_format(mut self, format: &str) -> GenerateError {
+        if self.format.is_none() {
+            self.format = Some(format.to_owned());
+        }
+        self
+    }
+
+    pub fn new(kind: &'static str, message: &'static str) -> GenerateError {
+        GenerateError{kind, message, part: None, format: None}
+    }
+}
+
+impl std::fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(part) = &self.part {
+            write!(f, " in part {}", part)?;
+        }
+        if let Some(format) = &self.format {
+            write!(f, " in format {}", format)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for GenerateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        self.message
     }
 }
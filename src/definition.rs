@@ -0,0 +1,565 @@
+use crate::{Name, NamePart, FormattingRule, NormalizationRule, Phonotactics, PhonotacticRule, SampleSet, Syllable, SyllableTemplate, Pattern};
+use crate::core::{ParseError, SyntaxError};
+
+/// A part declared in a definition, before it is built.
+struct PartDef {
+    line: usize,
+    name: String,
+    kind: Option<(usize, String)>,
+    tokens: Vec<String>,
+    order: Option<(usize, usize)>,
+    creativity: Option<(usize, f64)>,
+    constraints: Vec<(usize, String)>,
+    rules: Vec<FormattingRule>,
+    normalization: Vec<NormalizationRule>,
+    phonotactics: Phonotactics,
+    vowels: Option<(usize, String)>,
+    inventory: Vec<(usize, String, String)>,
+    pattern: Option<(usize, String)>,
+    symbols: Vec<(usize, char, Vec<String>)>,
+}
+
+/// Samples declared in a definition, either inline or as a file.
+struct SampleDef {
+    line: usize,
+    part_name: String,
+    blocks: bool,
+    source: SampleSource,
+}
+
+enum SampleSource {
+    Inline(String),
+    File(String),
+}
+
+enum Section {
+    None,
+    Part(usize),
+    Samples(usize),
+    Formats,
+}
+
+impl Name {
+    /// Build and train a name generator from a definition. See [`Name::from_definition_with_loader`]
+    /// for the syntax. Sample files cannot be used with this function, since there's nothing to
+    /// load them with.
+    pub fn from_definition(data: &str) -> Result<Name, ParseError> {
+        Self::from_definition_with_loader(data, |path| Err(format!("Cannot load {} without a loader", path)))
+    }
+
+    /// Build and train a name generator from a definition. The loader is called with the path of
+    /// each sample file, and errors it returns are reported at the line that referenced the file.
+    ///
+    /// ```text
+    /// # Lines starting with a hash are comments.
+    /// [part first]
+    /// type = markov
+    /// tokens = th ae ss nn
+    /// order = 3
+    /// creativity = 0.1
+    /// constraints = lre rtf
+    /// rule = capitalize-first
+    /// rule = "replace:_ "
+    /// normalize = nfc lowercase
+    /// class = liquid lr
+    /// phonotactic = max-run:consonant:2
+    /// phonotactic = not-end:liquid
+    /// words = first_names.txt
+    ///
+    /// [part last]
+    /// type = cfgrammar
+    /// constraints = rlf ral
+    /// rule = capitalize-first
+    ///
+    /// [words first]
+    /// aeris
+    /// baldur 4
+    ///
+    /// [blocks last]
+    /// SV * END
+    /// e le na
+    ///
+    /// [formats]
+    /// first_name = {first}
+    /// full_name = {first} {last}
+    /// ```
+    ///
+    /// A part's `type` is `markov`, `cfgrammar`, `wordlist`, `syllable` or `pattern`, and `constraints` takes
+    /// the flags of the matching constructor. `order` and `creativity` are only for `markov` parts, see
+    /// [`Markov::with_order`] and [`Markov::set_creativity`]. Formatting rules use the syntax of
+    /// [`FormattingRule::parse`], and `normalize` takes rules with the syntax of
+    /// [`NormalizationRule::parse`]. `class` adds a character class for the `phonotactic` rules,
+    /// which use the syntax of [`PhonotacticRule::parse`]. A `syllable` part takes its `vowels` for
+    /// learning, and can be written by hand with `templates`, `onsets`, `nuclei`, `codas` and
+    /// `syllables` (the syllable counts), where each item can have a weight like `CV*3`. A
+    /// `pattern` part takes a `pattern` with the syntax of [`Pattern`], and `symbol` adds a
+    /// character class to it as a letter followed by its items, e.g. `symbol = x ath ek`.
+    /// `words` and `blocks` in a part section load a sample file in the respective format, while
+    /// `[words ...]` and `[blocks ...]` sections contain the samples directly. Values can be put
+    /// in double quotes to keep surrounding whitespace.
+    ///
+    /// [`Markov::with_order`]: crate::Markov::with_order
+    /// [`Markov::set_creativity`]: crate::Markov::set_creativity
+    pub fn from_definition_with_loader<F>(data: &str, mut loader: F) -> Result<Name, ParseError>
+        where F: FnMut(&str) -> Result<String, String>
+    {
+        let mut parts: Vec<PartDef> = Vec::new();
+        let mut samples: Vec<SampleDef> = Vec::new();
+        let mut formats: Vec<(usize, String, String)> = Vec::new();
+        let mut section = Section::None;
+
+        for (i, raw_line) in data.lines().enumerate() {
+            let line_number = i + 1;
+            let line = raw_line.trim();
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let header: Vec<&str> = line[1..line.len() - 1].split_whitespace().collect();
+                section = match header.as_slice() {
+                    ["part", name] => {
+                        if parts.iter().any(|p| p.name == *name) {
+                            return Err(ParseError::new(line_number, format!("Part {} is already defined", name)));
+                        }
+
+                        parts.push(PartDef{
+                            line: line_number,
+                            name: (*name).to_owned(),
+                            kind: None,
+                            tokens: Vec::new(),
+                            order: None,
+                            creativity: None,
+                            constraints: Vec::new(),
+                            rules: Vec::new(),
+                            normalization: Vec::new(),
+                            phonotactics: Phonotactics::new(),
+                            vowels: None,
+                            inventory: Vec::new(),
+                            pattern: None,
+                            symbols: Vec::new(),
+                        });
+                        Section::Part(parts.len() - 1)
+                    }
+                    [kind @ "words", name] | [kind @ "blocks", name] => {
+                        samples.push(SampleDef{
+                            line: line_number,
+                            part_name: (*name).to_owned(),
+                            blocks: *kind == "blocks",
+                            source: SampleSource::Inline(String::new()),
+                        });
+                        Section::Samples(samples.len() - 1)
+                    }
+                    ["formats"] => Section::Formats,
+                    _ => return Err(ParseError::new(line_number, format!("Invalid section header: {}", line))),
+                };
+
+                continue;
+            }
+
+            // Sample sections are passed on as-is, blank lines and comments included.
+            if let Section::Samples(index) = section {
+                if let SampleSource::Inline(text) = &mut samples[index].source {
+                    text.push_str(raw_line);
+                    text.push('\n');
+                }
+                continue;
+            }
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), unquote(line[pos + 1..].trim())),
+                None => return Err(ParseError::new(line_number, format!("Expected key = value, found: {}", line))),
+            };
+
+            match section {
+                Section::Part(index) => {
+                    let part = &mut parts[index];
+
+                    match key {
+                        "type" => part.kind = Some((line_number, value.to_owned())),
+                        "tokens" => part.tokens.extend(value.split_whitespace().map(|s| s.to_owned())),
+                        "order" => match value.parse::<usize>() {
+                            Ok(order) if order > 0 => part.order = Some((line_number, order)),
+                            _ => return Err(ParseError::new(line_number, format!("Invalid order: {}", value))),
+                        },
+                        "creativity" => match value.parse::<f64>() {
+                            Ok(creativity) if (0.0..=1.0).contains(&creativity) => {
+                                part.creativity = Some((line_number, creativity))
+                            }
+                            _ => return Err(ParseError::new(line_number, format!("Invalid creativity: {}", value))),
+                        },
+                        "constraints" => part.constraints.extend(value.split_whitespace().map(|s| (line_number, s.to_owned()))),
+                        "rule" => match FormattingRule::parse(value) {
+                            Some(rule) => part.rules.push(rule),
+                            None => return Err(ParseError::new(line_number, format!("Invalid formatting rule: {}", value))),
+                        },
+                        "normalize" => for rule in value.split_whitespace() {
+                            match NormalizationRule::parse(rule) {
+                                Some(rule) => part.normalization.push(rule),
+                                None => return Err(ParseError::new(line_number, format!("Invalid normalization rule: {}", rule))),
+                            }
+                        },
+                        "class" => match value.split_once(' ') {
+                            Some((class, chars)) => part.phonotactics.add_class(class, chars.trim()),
+                            None => return Err(ParseError::new(line_number, format!("Expected class name and letters, found: {}", value))),
+                        },
+                        "phonotactic" => match PhonotacticRule::parse(value) {
+                            Some(rule) => part.phonotactics.add_rule(rule),
+                            None => return Err(ParseError::new(line_number, format!("Invalid phonotactic rule: {}", value))),
+                        },
+                        "vowels" => part.vowels = Some((line_number, value.to_owned())),
+                        "templates" | "onsets" | "nuclei" | "codas" | "syllables" => {
+                            part.inventory.push((line_number, key.to_owned(), value.to_owned()))
+                        }
+                        "pattern" => part.pattern = Some((line_number, value.to_owned())),
+                        "symbol" => {
+                            let mut items = value.split_whitespace();
+                            let mut symbol = items.next().unwrap_or("").chars();
+                            match (symbol.next(), symbol.next()) {
+                                (Some(c), None) if c.is_ascii_alphabetic() => {
+                                    part.symbols.push((line_number, c, items.map(|s| s.to_owned()).collect()))
+                                }
+                                _ => return Err(ParseError::new(line_number, format!("Expected a letter and its items, found: {}", value))),
+                            }
+                        }
+                        "words" | "blocks" => samples.push(SampleDef{
+                            line: line_number,
+                            part_name: part.name.clone(),
+                            blocks: key == "blocks",
+                            source: SampleSource::File(value.to_owned()),
+                        }),
+                        _ => return Err(ParseError::new(line_number, format!("Unknown part key: {}", key))),
+                    }
+                }
+                Section::Formats => {
+                    if formats.iter().any(|(_, name, _)| name == key) {
+                        return Err(ParseError::new(line_number, format!("Format {} is already defined", key)));
+                    }
+
+                    formats.push((line_number, key.to_owned(), value.to_owned()));
+                }
+                _ => return Err(ParseError::new(line_number, "Expected a section header".to_owned())),
+            }
+        }
+
+        let mut name = Name::new();
+        for part in parts.iter() {
+            name.add_part(build_part(part)?);
+        }
+
+        for sample in samples.iter() {
+            if !name.has_part_name(&sample.part_name) {
+                return Err(ParseError::new(sample.line, format!("Part {} is not defined", sample.part_name)));
+            }
+
+            let (text, first_line) = match &sample.source {
+                SampleSource::Inline(text) => (text.clone(), sample.line + 1),
+                SampleSource::File(path) => match loader(path) {
+                    Ok(text) => (text, 1),
+                    Err(err) => return Err(ParseError::new(sample.line, err)),
+                },
+            };
+
+            let sets = if sample.blocks {
+                SampleSet::parse_blocks(&text)
+            } else {
+                SampleSet::parse_words(&text).map(|set| vec![set])
+            };
+            let sets = match (sets, &sample.source) {
+                (Ok(sets), _) => sets,
+                (Err(err), SampleSource::Inline(_)) => {
+                    return Err(err.with_offset(first_line - 1));
+                }
+                (Err(err), SampleSource::File(path)) => {
+                    return Err(ParseError::new(sample.line, format!("{}: {}", path, err)));
+                }
+            };
+
+            for set in sets.iter() {
+                if let Err(err) = name.learn(&sample.part_name, set) {
+                    return Err(ParseError::new(sample.line, err.to_string()));
+                }
+            }
+        }
+
+        for (line, format_name, format) in formats.iter() {
+            if format.is_empty() {
+                return Err(ParseError::new(*line, format!("Format {} is empty", format_name)));
+            }
+
+            name.add_format(format_name, format)
+                .map_err(|err| ParseError::new(*line, format!("Invalid format at column {}: {}", column(format, &err), err.message())))?;
+        }
+        if let Err((index, err)) = name.validate_formats() {
+            return Err(ParseError::new(formats[index].0, err.to_string()));
+        }
+
+        Ok(name)
+    }
+}
+
+fn build_part(part: &PartDef) -> Result<NamePart, ParseError> {
+    let (kind_line, kind) = match &part.kind {
+        Some((line, kind)) => (*line, kind.as_str()),
+        None => return Err(ParseError::new(part.line, format!("Part {} has no type", part.name))),
+    };
+
+    let allowed: &[&str] = match kind {
+        "markov" => &["lrs", "lrm", "lre", "rtf"],
+        "cfgrammar" => &["rlf", "ral"],
+        "wordlist" | "syllable" | "pattern" => &[],
+        _ => return Err(ParseError::new(kind_line, format!("Unknown part type: {}", kind))),
+    };
+    for (line, constraint) in part.constraints.iter() {
+        if !allowed.contains(&constraint.as_str()) {
+            return Err(ParseError::new(*line, format!("Unknown constraint for {}: {}", kind, constraint)));
+        }
+    }
+    if (kind == "wordlist" || kind == "syllable" || kind == "pattern") && !part.tokens.is_empty() {
+        return Err(ParseError::new(kind_line, format!("Part type {} does not use tokens", kind)));
+    }
+    if kind != "syllable" {
+        if let Some(line) = part.vowels.as_ref().map(|(l, _)| *l).or_else(|| part.inventory.first().map(|(l, _, _)| *l)) {
+            return Err(ParseError::new(line, format!("Part type {} does not have syllables", kind)));
+        }
+    }
+    if kind != "pattern" {
+        if let Some(line) = part.pattern.as_ref().map(|(l, _)| *l).or_else(|| part.symbols.first().map(|(l, _, _)| *l)) {
+            return Err(ParseError::new(line, format!("Part type {} does not have a pattern", kind)));
+        }
+    }
+    if let Some((line, _)) = part.order {
+        if kind != "markov" {
+            return Err(ParseError::new(line, format!("Part type {} does not have an order", kind)));
+        }
+    }
+    if let Some((line, _)) = part.creativity {
+        if kind != "markov" {
+            return Err(ParseError::new(line, format!("Part type {} does not have a creativity", kind)));
+        }
+    }
+
+    let has = |c: &str| part.constraints.iter().any(|(_, c2)| c2 == c);
+
+    let mut res = match kind {
+        "markov" => NamePart::new_markov_with_order(
+            &part.name, &part.rules, &part.tokens, part.order.map(|(_, o)| o).unwrap_or(2),
+            has("lrs"), has("lrm"), has("lre"), has("rtf"),
+        ),
+        "cfgrammar" => NamePart::new_cfgrammar(&part.name, &part.rules, &part.tokens, has("rlf"), has("ral")),
+        "syllable" => NamePart::new_syllable(&part.name, &part.rules, build_syllable(part)?),
+        "pattern" => NamePart::new_pattern(&part.name, &part.rules, build_pattern(part, kind_line)?),
+        _ => NamePart::new_wordlist(&part.name, &part.rules),
+    };
+    if let Some((_, creativity)) = part.creativity {
+        res.set_creativity(creativity);
+    }
+    res.set_normalization(&part.normalization);
+    res.set_phonotactics(part.phonotactics.clone());
+
+    Ok(res)
+}
+
+fn build_syllable(part: &PartDef) -> Result<Syllable, ParseError> {
+    let mut syllable = match &part.vowels {
+        Some((_, vowels)) => Syllable::with_vowels(vowels),
+        None => Syllable::new(),
+    };
+
+    for (line, key, value) in part.inventory.iter() {
+        for item in value.split_whitespace() {
+            let (item, weight) = match item.split_once('*') {
+                Some((item, weight)) => match weight.parse::<u32>() {
+                    Ok(weight) if weight > 0 => (item, weight),
+                    _ => return Err(ParseError::new(*line, format!("Invalid weight: {}", item))),
+                },
+                None => (item, 1),
+            };
+
+            let added = match key.as_str() {
+                "templates" => match SyllableTemplate::parse(item) {
+                    Some(template) => syllable.add_template(template, weight),
+                    None => return Err(ParseError::new(*line, format!("Invalid syllable template: {}", item))),
+                },
+                "onsets" => syllable.add_onset(item, weight),
+                "nuclei" => syllable.add_nucleus(item, weight),
+                "codas" => syllable.add_coda(item, weight),
+                _ => match item.parse::<usize>() {
+                    Ok(length) if length > 0 => syllable.add_length(length, weight),
+                    _ => return Err(ParseError::new(*line, format!("Invalid syllable count: {}", item))),
+                },
+            };
+            if !added {
+                return Err(ParseError::new(*line, format!("The {} weights add up to more than {}", key, u32::MAX)));
+            }
+        }
+    }
+
+    Ok(syllable)
+}
+
+fn build_pattern(part: &PartDef, kind_line: usize) -> Result<Pattern, ParseError> {
+    let (line, source) = match &part.pattern {
+        Some((line, source)) => (*line, source),
+        None => return Err(ParseError::new(kind_line, format!("Part {} has no pattern", part.name))),
+    };
+
+    let classes: Vec<(char, &[String])> = part.symbols.iter().map(|(_, c, items)| (*c, items.as_slice())).collect();
+    Pattern::parse_with_classes(source, &classes)
+        .map_err(|err| ParseError::new(line, format!("Invalid pattern at column {}: {}", column(source, &err), err.message())))
+}
+
+/// Get the column of a syntax error, counted in characters from 1.
+fn column(source: &str, err: &SyntaxError) -> usize {
+    source.get(..err.offset()).map(|s| s.chars().count()).unwrap_or(err.offset()) + 1
+}
+
+fn unquote(s: &str) -> &str {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINITION: &str = "
+# Test definition
+[part first]
+type = markov
+tokens = th ae
+constraints = lre rtf
+rule = capitalize-first
+
+[part last]
+type = cfgrammar
+constraints = rlf ral
+rule = capitalize-first
+rule = \"replace:_ \"
+blocks = last.txt
+
+[words first]
+aeris
+baldur 2
+celia
+# comment
+dorian
+
+[formats]
+full_name = {first} {last}
+";
+
+    #[test]
+    fn test_from_definition() {
+        let name = Name::from_definition_with_loader(DEFINITION, |path| {
+            assert_eq!(path, "last.txt");
+            Ok("* *\nstone wall\niron_ forge\n".to_owned())
+        }).unwrap();
+
+        name.validate().unwrap();
+        assert!(name.has_part_name("first"));
+        assert!(name.has_part_name("last"));
+        assert_eq!(name.first_format_name(), Some("full_name"));
+
+        for result in name.generate("full_name").unwrap().take(20) {
+            assert!(result.chars().next().unwrap().is_uppercase(), "{}", result);
+            assert!(result.contains(' '), "{}", result);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Name::from_definition(DEFINITION).err().unwrap().line(), 14);
+        assert_eq!(error_line("[part first]\ntype = markov\n\n[part first]\ntype = markov"), 4);
+        assert_eq!(error_line("[part first]\ntype = markovv"), 2);
+        assert_eq!(error_line("\n[part first]\nrule = capitalize-first"), 2);
+        assert_eq!(error_line("[part first]\ntype = markov\nconstraints = lre ral"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nrule = capitalize"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nstuff"), 3);
+        assert_eq!(error_line("type = markov"), 1);
+        assert_eq!(error_line("[words first]\naeris"), 1);
+        assert_eq!(error_line("[part first]\ntype = wordlist\n[words first]\naeris\n\nbaldur x"), 6);
+        assert_eq!(error_line("[part first]\ntype = markov\n[words first]\naeris\nab"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\n[stuff]"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\norder = 0"), 3);
+        assert_eq!(error_line("[part first]\ntype = wordlist\norder = 3"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\ncreativity = 1.5"), 3);
+        assert_eq!(error_line("[part first]\ntype = cfgrammar\ncreativity = 0.5"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nnormalize = nfc nfkc"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nclass = liquid"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nphonotactic = max-run:vowel"), 3);
+        assert_eq!(error_line("[part first]\ntype = markov\nonsets = k t"), 3);
+        assert_eq!(error_line("[part first]\ntype = syllable\ntemplates = CV CCV"), 3);
+        assert_eq!(error_line("[part first]\ntype = syllable\nnuclei = a*0"), 3);
+        assert_eq!(error_line("[part first]\ntype = syllable\nsyllables = 0"), 3);
+        assert_eq!(error_line("[part first]\ntype = wordlist\n[formats]\nfull = {frist}"), 4);
+        assert_eq!(error_line("[part first]\ntype = wordlist\n[formats]\nfirst = {first}\nfull = {:frist}"), 5);
+        assert_eq!(error_line("[part first]\ntype = wordlist\n[formats]\nfirst = {first}\nfull = {:full}"), 5);
+        assert_eq!(error_line("[part first]\ntype = pattern"), 2);
+        assert_eq!(error_line("[part first]\ntype = pattern\n\npattern = <B><X>"), 4);
+        assert_eq!(error_line("[part first]\ntype = pattern\nsymbol = xy a b"), 3);
+        assert_eq!(error_line("[part first]\ntype = wordlist\npattern = <s>"), 3);
+        assert_eq!(error_line("[part first]\ntype = pattern\npattern = <s>\n[words first]\naeris"), 4);
+    }
+
+    #[test]
+    fn test_syllable() {
+        let name = Name::from_definition("
+[part first]
+type = syllable
+templates = CV*3 CVC
+onsets = k t th*2
+nuclei = a e ai
+codas = n
+syllables = 2 3
+rule = capitalize-first
+
+[formats]
+first = {first}
+").unwrap();
+
+        name.validate().unwrap();
+        for result in name.generate("first").unwrap().take(20) {
+            assert!(name.score(&result).is_some(), "{}", result);
+        }
+    }
+
+    #[test]
+    fn test_pattern() {
+        let name = Name::from_definition("
+[part first]
+type = wordlist
+
+[part epithet]
+type = pattern
+pattern = \"<x|y>'<B>V\"
+symbol = x Kor Dal
+symbol = y Vess
+rule = capitalize-first
+
+[words first]
+aeris
+baldur
+
+[formats]
+full = {first} {epithet}
+").unwrap();
+
+        name.validate().unwrap();
+        for result in name.generate("full").unwrap().take(20) {
+            let (_, epithet) = result.split_once(' ').unwrap();
+            assert!(epithet.starts_with("Kor'") || epithet.starts_with("Dal'") || epithet.starts_with("Vess'"), "{}", result);
+        }
+
+        let err = Name::from_definition("[part first]\ntype = pattern\npattern = <s>(ar").err().unwrap();
+        assert!(err.to_string().contains("column 4"), "{}", err);
+    }
+
+    fn error_line(data: &str) -> usize {
+        Name::from_definition(data).err().unwrap().line()
+    }
+}
@@ -0,0 +1,190 @@
+type Predicate = Box<dyn Fn(&str) -> bool>;
+
+pub(crate) const DEFAULT_MAX_RETRIES: usize = 1000;
+
+/// A NameFilter decides which generated names to reject. Add it to a [`GeneratorIter`] with
+/// [`GeneratorIter::with_filter`], and rejected names will be regenerated until one passes or the
+/// retry budget is spent.
+///
+/// Substrings and patterns are matched case-insensitively, and lengths are counted in characters.
+///
+/// [`GeneratorIter`]: crate::GeneratorIter
+/// [`GeneratorIter::with_filter`]: crate::GeneratorIter::with_filter
+pub struct NameFilter {
+    min_length: usize,
+    max_length: usize,
+    forbidden: Vec<String>,
+    patterns: Vec<Vec<char>>,
+    predicates: Vec<Predicate>,
+    max_retries: usize,
+}
+
+impl NameFilter {
+    /// Check a name against the filter.
+    pub fn accepts(&self, name: &str) -> bool {
+        let length = name.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return false;
+        }
+
+        if !self.forbidden.is_empty() || !self.patterns.is_empty() {
+            let lowercase = name.to_lowercase();
+            if self.forbidden.iter().any(|s| lowercase.contains(s.as_str())) {
+                return false;
+            }
+
+            let chars: Vec<char> = lowercase.chars().collect();
+            if self.patterns.iter().any(|p| glob_match(p, &chars)) {
+                return false;
+            }
+        }
+
+        self.predicates.iter().all(|p| p(name))
+    }
+
+    /// How many times in a row a name can be rejected before the iterator gives up.
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Reject names shorter than `min` or longer than `max` characters.
+    pub fn with_length(mut self, min: usize, max: usize) -> NameFilter {
+        self.min_length = min;
+        self.max_length = max;
+        self
+    }
+
+    /// Reject names containing any of these substrings, e.g. a profanity list.
+    pub fn with_forbidden<S: AsRef<str>>(mut self, substrings: &[S]) -> NameFilter {
+        self.forbidden.extend(substrings.iter().map(|s| s.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Reject names matching this pattern. The pattern must match the whole name, and it supports
+    /// `?` for any character, `*` for any number of characters, and `[...]` or `[^...]` for
+    /// character sets. E.g. `*[^aeiou][^aeiou][^aeiou]*` rejects three consonants in a row.
+    pub fn with_pattern(mut self, pattern: &str) -> NameFilter {
+        self.patterns.push(pattern.to_lowercase().chars().collect());
+        self
+    }
+
+    /// Reject names for which the predicate returns false.
+    pub fn with_predicate<F>(mut self, predicate: F) -> NameFilter where F: Fn(&str) -> bool + 'static {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// Set how many times in a row a name can be rejected before the iterator gives up. The
+    /// default is 1000.
+    pub fn with_max_retries(mut self, max_retries: usize) -> NameFilter {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn new() -> NameFilter {
+        NameFilter{
+            min_length: 0,
+            max_length: usize::MAX,
+            forbidden: Vec::new(),
+            patterns: Vec::new(),
+            predicates: Vec::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+}
+
+impl Default for NameFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn glob_match(pattern: &[char], s: &[char]) -> bool {
+    match pattern.first() {
+        None => s.is_empty(),
+        Some('*') => (0..=s.len()).any(|i| glob_match(&pattern[1..], &s[i..])),
+        Some('?') => !s.is_empty() && glob_match(&pattern[1..], &s[1..]),
+        Some('[') => {
+            let end = match pattern.iter().position(|c| *c == ']') {
+                Some(end) => end,
+                None => return !s.is_empty() && s[0] == '[' && glob_match(&pattern[1..], &s[1..]),
+            };
+            if s.is_empty() {
+                return false;
+            }
+
+            let (negated, set) = match pattern[1..end].split_first() {
+                Some(('^', set)) => (true, set),
+                _ => (false, &pattern[1..end]),
+            };
+            set.contains(&s[0]) != negated && glob_match(&pattern[end + 1..], &s[1..])
+        }
+        Some(c) => !s.is_empty() && s[0] == *c && glob_match(&pattern[1..], &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Name, NamePart, SampleSet, Sample};
+
+    #[test]
+    fn test_accepts() {
+        let filter = NameFilter::new()
+            .with_length(3, 6)
+            .with_forbidden(&["bad"])
+            .with_pattern("*[^aeiou][^aeiou][^aeiou]*")
+            .with_predicate(|s| !s.starts_with('x'));
+
+        assert!(filter.accepts("aeris"));
+        assert!(filter.accepts("Ærin"));
+        assert!(!filter.accepts("ae"));
+        assert!(!filter.accepts("aerisan"));
+        assert!(!filter.accepts("Badia"));
+        assert!(!filter.accepts("astra"));
+        assert!(!filter.accepts("xenia"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(&chars("a*"), &chars("aeris")));
+        assert!(glob_match(&chars("?eri?"), &chars("aeris")));
+        assert!(glob_match(&chars("[ab]*"), &chars("baldur")));
+        assert!(glob_match(&chars("[^c]*"), &chars("baldur")));
+        assert!(glob_match(&chars("[a*"), &chars("[aeris")));
+        assert!(!glob_match(&chars("a*"), &chars("baldur")));
+        assert!(!glob_match(&chars("[^b]*"), &chars("baldur")));
+        assert!(!glob_match(&chars("?"), &chars("")));
+    }
+
+    #[test]
+    fn test_generator_iter() {
+        let mut name = Name::new();
+        let mut part = NamePart::new_wordlist("first", &[]);
+        let mut sample_set = SampleSet::new();
+        for word in ["aeris", "baldur", "celia", "dorian"].iter() {
+            sample_set.add_sample(Sample::Word((*word).to_owned()));
+        }
+        part.learn(&sample_set).unwrap();
+        name.add_part(part);
+        name.add_format("first", "{first}").unwrap();
+
+        let mut iter = name.generate_seeded(42, "first").unwrap()
+            .with_filter(NameFilter::new().with_length(0, 5));
+        for result in (&mut iter).take(50) {
+            assert!(result.len() <= 5, "{}", result);
+        }
+        assert!(iter.rejections() > 0);
+        assert!(!iter.is_exhausted());
+
+        let mut iter = name.generate_seeded(42, "first").unwrap()
+            .with_filter(NameFilter::new().with_length(10, 20).with_max_retries(10));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.rejections(), 11);
+        assert!(iter.is_exhausted());
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+}
@@ -11,6 +11,28 @@ pub enum FormattingRule {
     ReplaceChar{from: char, to: char},
 }
 
+impl FormattingRule {
+    /// Parse a rule from its short form, which is `capitalize-first`, `capitalize-default`,
+    /// `capitalize-after:<c>`, `remove:<c>` or `replace:<from><to>`. This is the syntax used by
+    /// definition files and the command-line tool.
+    pub fn parse(s: &str) -> Option<FormattingRule> {
+        let (kind, arg) = match s.find(':') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let chars: Vec<char> = arg.map(|a| a.chars().collect()).unwrap_or_default();
+
+        match (kind, chars.as_slice()) {
+            ("capitalize-first", []) => Some(FormattingRule::CapitalizeFirst),
+            ("capitalize-default", []) => Some(FormattingRule::CapitalizeDefault),
+            ("capitalize-after", [ch]) => Some(FormattingRule::CapitalizeAfter(*ch)),
+            ("remove", [ch]) => Some(FormattingRule::RemoveChar(*ch)),
+            ("replace", [from, to]) => Some(FormattingRule::ReplaceChar{from: *from, to: *to}),
+            _ => None,
+        }
+    }
+}
+
 /// Format WorkingSet's content.
 pub fn format_ws(ws: &mut WorkingSet, rules: &[FormattingRule]) {
     ws.result_chars.clear();
@@ -23,7 +45,6 @@ pub fn format_ws(ws: &mut WorkingSet, rules: &[FormattingRule]) {
 }
 
 /// Format string. This does two allocations.
-#[allow(dead_code)]
 pub fn format_string(s: &str, rules: &[FormattingRule]) -> String {
     let mut chars = s.chars().collect();
     format_vec(&mut chars, rules);
@@ -34,7 +55,7 @@ pub fn format_string(s: &str, rules: &[FormattingRule]) -> String {
 /// Format the content of the vector with the given rules. This
 /// does not allocate.
 pub fn format_vec(v: &mut Vec<char>, rules: &[FormattingRule]) {
-    if v.len() == 0 || rules.len() == 0 {
+    if v.is_empty() || rules.is_empty() {
         return;
     }
 
@@ -74,9 +95,9 @@ pub fn format_vec(v: &mut Vec<char>, rules: &[FormattingRule]) {
     for rule in rules.iter() {
         match *rule {
             FormattingRule::ReplaceChar {from, to} => {
-                for i in 0..v.len() {
-                    if v[i] == from {
-                        v[i] = to;
+                for c in v.iter_mut() {
+                    if *c == from {
+                        *c = to;
                     }
                 }
             }
@@ -127,6 +148,15 @@ mod tests {
         assert_eq!(format_string("straße", &[FormattingRule::CapitalizeDefault]), "STRASSE");
     }
 
+    #[test]
+    fn test_parse() {
+        assert!(FormattingRule::parse("capitalize-first") == Some(FormattingRule::CapitalizeFirst));
+        assert!(FormattingRule::parse("capitalize-after:'") == Some(FormattingRule::CapitalizeAfter('\'')));
+        assert!(FormattingRule::parse("replace:_ ") == Some(FormattingRule::ReplaceChar{from: '_', to: ' '}));
+        assert!(FormattingRule::parse("remove:ab").is_none());
+        assert!(FormattingRule::parse("capitalize-first:a").is_none());
+    }
+
     #[test]
     fn test_multiple_capitalization() {
         assert_eq!(
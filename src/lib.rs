@@ -3,19 +3,38 @@
 extern crate serde;
 
 pub use crate::sample::{Sample, SampleSet};
-pub use crate::core::{WorkingSet, LearnError};
+pub use crate::core::{WorkingSet, LearnError, ParseError, GenerateError, SyntaxError};
 pub use crate::formatting::{FormattingRule, format_string};
-pub use crate::cfgrammar::CFGrammar;
+pub use crate::normalize::{NormalizationRule, normalize_string};
+pub use crate::phonotactics::{Phonotactics, PhonotacticRule};
+pub use crate::cfgrammar::{CFGrammar, CFGrammarOutputs};
 pub use crate::markov::Markov;
 pub use crate::wordlist::WordList;
-pub use crate::name::{Name, NameFormat};
+pub use crate::syllable::{Syllable, SyllableTemplate};
+pub use crate::pattern::Pattern;
+pub use crate::name::{Name, NameFormat, GeneratorIter};
+pub use crate::filter::NameFilter;
+pub use crate::classify::{Classifier, Match};
 pub use crate::part::{NamePart};
 
+mod classify;
+mod definition;
+mod filter;
 mod formatting;
 mod core;
 mod cfgrammar;
 mod wordlist;
 mod markov;
 mod name;
+mod normalize;
 mod part;
+mod pattern;
+mod phonotactics;
 mod sample;
+mod samplefile;
+mod syllable;
+mod tokenizer;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,7 +1,9 @@
 use rand::{Rng};
-use crate::{Sample, SampleSet, WorkingSet, LearnError};
-use crate::core::ValidationError;
-use std::collections::HashSet;
+use crate::{Sample, SampleSet, WorkingSet, LearnError, Phonotactics};
+use crate::core::{ValidationError, GenerateError, DEFAULT_MAX_ATTEMPTS};
+use crate::tokenizer::{Tokenizer, first_grapheme};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::OnceLock;
 
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -14,6 +16,15 @@ pub struct Markov {
     nodes: Vec<Node>,
     lengths: Vec<usize>,
     total_lengths: usize,
+    #[cfg_attr(feature = "serde", serde(default = "default_order"))]
+    order: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="is_zero_f64"))]
+    creativity: f64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: OnceLock<Box<Index>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tokenizer: OnceLock<Tokenizer>,
 
     lrs: bool,
     lrm: bool,
@@ -48,9 +59,8 @@ impl Markov {
     #[allow(dead_code)]
     pub fn print(&self) {
         for start in self.starts.iter() {
-            println!("{}{} l={} w={}", 
-                self.tokens[start.tokens.0],
-                self.tokens[start.tokens.1],
+            println!("{} l={} w={}",
+                start.tokens.iter().map(|t| self.tokens[*t].as_str()).collect::<String>(),
                 start.length,
                 start.weight,
             );
@@ -61,17 +71,25 @@ impl Markov {
         }
     }
 
-    fn find_next_token(&self, remainder: &str) -> Option<usize> {       
-        self.tokens.iter().enumerate().skip(1)
-                   .filter(|(_, t)| remainder.starts_with(*t))
-                   .map(|(i, _)| i )
-                   .next()
+    /// Get the tokenizer, which is built on first use after the generator is deserialized.
+    fn tokenizer(&self) -> &Tokenizer {
+        self.tokenizer.get_or_init(|| Tokenizer::new(&self.tokens))
+    }
+
+    /// Add new tokens to the tokenizer, or build it if it's missing.
+    fn sync_tokenizer(&mut self) {
+        match self.tokenizer.get_mut() {
+            Some(tokenizer) => tokenizer.sync(&self.tokens),
+            None => {
+                self.tokenizer();
+            }
+        }
     }
 
     fn pick_length(&self, rng: &mut impl Rng) -> usize {
         let mut random = rng.gen_range(0, self.total_lengths);
 
-        3 + self.lengths.iter().enumerate().filter(|(_, s)| {
+        self.order + 1 + self.lengths.iter().enumerate().filter(|(_, s)| {
             if **s > random {
                 true
             } else {
@@ -96,43 +114,83 @@ impl Markov {
 
     /// Generate a name. You need to provide your own WorkingSet and Rng, which is necessary to save
     /// on allocations. A dependent application should use the full name generator interface instead
+    ///
+    /// If no name is found after 1024 restarts, the result is left empty. Use `try_generate` to
+    /// pick the budget and find out why.
     pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
-        if self.starts.len() == 0 {
-            return
+        if self.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS).is_err() {
+            ws.result_str.clear();
+        }
+    }
+
+    /// Generate a name like `generate`, but return an error if nothing is learned, or if no name
+    /// is found after `max_attempts` restarts from a new start.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize) -> Result<(), GenerateError> {
+        self.try_generate_with(ws, rng, max_attempts, &Phonotactics::new())
+    }
+
+    /// Generate a name like `try_generate` that follows the phonotactic rules. Each token is
+    /// checked as it's added, and the chain backtracks as soon as a rule is broken.
+    pub fn try_generate_with(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
+        if self.starts.is_empty() || self.total_lengths == 0 {
+            return Err(GenerateError::new("parts::Markov", "Nothing has been learned."));
         }
 
         let mut length = 1;
+        let mut attempts = 0;
 
         ws.stack_pos.clear();
+        ws.stack_weight.clear();
+        ws.stack_backoff.clear();
         ws.result.clear();
 
         while ws.result.len() < length {
             // Start if the stack is empty.
-            if ws.stack_pos.len() == 0 {
+            if ws.stack_pos.is_empty() {
+                if attempts == max_attempts {
+                    return Err(GenerateError::new("parts::Markov", "No valid output found within the attempt budget."));
+                }
+                attempts += 1;
+
                 let start_index = self.pick_start(rng);
                 let start = &self.starts[start_index];
 
                 ws.result.clear();
                 ws.stack.clear();
 
-                ws.result.push(start.tokens.0);
-                ws.result.push(start.tokens.1);
+                ws.result.extend(start.tokens.iter());
+                if !self.check_phonotactics(ws, phonotactics, false) {
+                    ws.result.clear();
+                    continue;
+                }
                 ws.stack.extend(start.children.iter());
                 ws.stack_pos.push(0);
                 ws.stack_weight.push(start.children.iter().map(|ci| self.nodes[*ci].weight).sum());
+                ws.stack_backoff.push(false);
 
                 length = if self.lrs { start.length } else { self.pick_length(rng) };
+
+                if self.creativity > 0.0 && rng.gen::<f64>() < self.creativity {
+                    self.back_off(ws, length);
+                }
             }
 
             // Get the last one.
             let pos = *ws.stack_pos.last().unwrap();
-            let weight = *ws.stack_weight.last().unwrap();
             if ws.stack.len() == pos {
+                // Try a shorter context before giving up on this one.
+                if self.creativity > 0.0 && !*ws.stack_backoff.last().unwrap() {
+                    self.back_off(ws, length);
+                    continue;
+                }
+
                 ws.stack_pos.pop();
                 ws.stack_weight.pop();
+                ws.stack_backoff.pop();
                 ws.result.pop();
                 continue;
             }
+            let weight = *ws.stack_weight.last().unwrap();
 
             // Pick a available child node.
             let mut r = rng.gen_range(0, weight);
@@ -173,15 +231,205 @@ impl Markov {
 
             // Push the token
             ws.result.push(node.token);
+            if !self.check_phonotactics(ws, phonotactics, ending) {
+                ws.result.pop();
+                continue;
+            }
             ws.stack_pos.push(ws.stack.len());
             ws.stack_weight.push(node.children.iter().map(|ci| self.nodes[*ci].weight).sum());
+            ws.stack_backoff.push(false);
             ws.stack.extend(node.children.iter());
+
+            if self.creativity > 0.0 && ws.result.len() < length && rng.gen::<f64>() < self.creativity {
+                self.back_off(ws, length);
+            }
         };
 
         ws.result_str.clear();
         for s in ws.result.iter().map(|i| &self.tokens[*i]) {
             ws.result_str.push_str(s);
         }
+
+        Ok(())
+    }
+
+    /// Check the result so far against the phonotactic rules, using `result_str` as scratch space.
+    fn check_phonotactics(&self, ws: &mut WorkingSet, phonotactics: &Phonotactics, complete: bool) -> bool {
+        if phonotactics.is_empty() {
+            return true;
+        }
+
+        ws.result_str.clear();
+        for s in ws.result.iter().map(|i| &self.tokens[*i]) {
+            ws.result_str.push_str(s);
+        }
+
+        if complete {
+            phonotactics.check(&ws.result_str)
+        } else {
+            phonotactics.check_prefix(&ws.result_str)
+        }
+    }
+
+    /// Check whether any start can reach an ending with one of the lengths it can be given, which
+    /// `try_generate` needs to succeed. The token frequency restriction is not considered, since
+    /// the samples themselves always satisfy it.
+    pub fn can_generate(&self) -> bool {
+        let mut dead_ends: HashSet<(usize, usize, usize)> = HashSet::new();
+
+        for start in self.starts.iter() {
+            let lengths: Vec<usize> = if self.lrs {
+                vec![start.length]
+            } else {
+                self.lengths.iter().enumerate()
+                    .filter(|(_, count)| **count > 0)
+                    .map(|(i, _)| i + self.order + 1)
+                    .collect()
+            };
+
+            for length in lengths {
+                if self.can_reach_ending(&start.children, self.order, length, &mut dead_ends) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn can_reach_ending(&self, children: &[usize], pos: usize, length: usize, dead_ends: &mut HashSet<(usize, usize, usize)>) -> bool {
+        let ending = pos == length - 1;
+
+        for child in children.iter().cloned() {
+            let node = &self.nodes[child];
+            if node.ending != ending || (self.lre && ending && node.length != length) {
+                continue;
+            }
+            if ending {
+                return true;
+            }
+
+            if dead_ends.contains(&(child, pos, length)) {
+                continue;
+            }
+            if self.can_reach_ending(&node.children, pos + 1, length, dead_ends) {
+                return true;
+            }
+            dead_ends.insert((child, pos, length));
+        }
+
+        false
+    }
+
+    /// Replace the candidates of the current stack level with every node that follows the last
+    /// `order - 1` tokens, which includes the nodes the full context could not reach.
+    fn back_off(&self, ws: &mut WorkingSet, length: usize) {
+        let pos = *ws.stack_pos.last().unwrap();
+        let context = &ws.result[ws.result.len() + 1 - self.order..];
+
+        ws.stack.truncate(pos);
+        let mut weight = 0;
+        for i in self.index().backoff_candidates(context) {
+            let node = &self.nodes[i];
+            if !(self.lrm && !node.ending && node.length != length) {
+                ws.stack.push(i);
+                weight += node.weight;
+            }
+        }
+
+        *ws.stack_weight.last_mut().unwrap() = weight;
+        *ws.stack_backoff.last_mut().unwrap() = true;
+    }
+
+    /// Get the likelihood of the generator producing this exact string, or `None` if it cannot
+    /// produce it at all. This follows the same weights as `generate`, but it does not account for
+    /// the retries that happen when a chain dead-ends, so the value is an approximation. With
+    /// creativity, every step mixes in the shorter context with the creativity as its weight.
+    pub fn score(&self, s: &str) -> Option<f64> {
+        let tokenizer = self.tokenizer();
+        let mut remainder = s;
+        let mut tokens: Vec<usize> = Vec::with_capacity(s.len());
+        while !remainder.is_empty() {
+            let (index, len) = tokenizer.longest_match(remainder)?;
+            remainder = &remainder[len..];
+            tokens.push(index);
+        }
+        if tokens.len() <= self.order || self.total_starts == 0 {
+            return None;
+        }
+
+        if self.rtf {
+            for token in tokens.iter() {
+                if tokens.iter().filter(|t| *t == token).count() > self.max_tokens[*token] {
+                    return None;
+                }
+            }
+        }
+
+        let length_p = if self.lrs {
+            1.0
+        } else {
+            match self.lengths.get(tokens.len() - self.order - 1) {
+                Some(count) if *count > 0 => *count as f64 / self.total_lengths as f64,
+                _ => return None,
+            }
+        };
+
+        let start_length = if self.lrs { tokens.len() } else { 0 };
+        let mut p = 0.0;
+        for start in self.starts.iter() {
+            if start.tokens[..] == tokens[..self.order] && start.length == start_length {
+                let start_p = start.weight as f64 / self.total_starts as f64;
+                p += start_p * self.score_children(&start.children, &tokens, self.order);
+            }
+        }
+
+        if p > 0.0 {
+            Some(p * length_p)
+        } else {
+            None
+        }
+    }
+
+    fn score_children(&self, children: &[usize], tokens: &[usize], pos: usize) -> f64 {
+        let p = self.score_candidates(children.iter().cloned(), tokens, pos);
+        if self.creativity == 0.0 {
+            return p;
+        }
+
+        let context = &tokens[pos + 1 - self.order..pos];
+        let backoff = self.index().backoff_candidates(context).filter(|i| {
+            let node = &self.nodes[*i];
+            !(self.lrm && !node.ending && node.length != tokens.len())
+        });
+
+        (1.0 - self.creativity) * p + self.creativity * self.score_candidates(backoff, tokens, pos)
+    }
+
+    fn score_candidates(&self, candidates: impl Iterator<Item=usize> + Clone, tokens: &[usize], pos: usize) -> f64 {
+        let ending = pos == tokens.len() - 1;
+        let valid = |node: &&Node| node.ending == ending && !(self.lre && ending && node.length != tokens.len());
+
+        let total: usize = candidates.clone().map(|i| &self.nodes[i]).filter(valid).map(|n| n.weight).sum();
+        if total == 0 {
+            return 0.0;
+        }
+
+        let mut p = 0.0;
+        for node in candidates.map(|i| &self.nodes[i]).filter(valid) {
+            if node.token != tokens[pos] {
+                continue;
+            }
+
+            let node_p = node.weight as f64 / total as f64;
+            if ending {
+                p += node_p;
+            } else {
+                p += node_p * self.score_children(&node.children, tokens, pos + 1);
+            }
+        }
+
+        p
     }
 
     /// Learn learns samples from the sample set. The former state is copied and will
@@ -203,51 +451,51 @@ impl Markov {
     /// Learn rules from the sample. The generation is heavily optimized for speed, but `learn` is
     /// paying for that speed.
     pub fn learn_one(&mut self, sample: &Sample) -> Result<(), LearnError> {
-        if let Err(err) = self.learn_norecalc(sample) {
-            return Err(err)
-        }
-
+        self.learn_norecalc(sample)?;
         self.recalculate_weights();
 
         Ok(())
     }
 
     fn learn_norecalc(&mut self, sample: &Sample) -> Result<(), LearnError> {
-        let sample_string: &str;
-        match sample {
-            Sample::Word(s) => sample_string = &s,
-            Sample::WordWeighted(s, _) => sample_string = &s,
+        let sample_string: &str = match sample {
+            Sample::Word(s) => s,
+            Sample::WordWeighted(s, _) => s,
             _ => {
                 return Err(LearnError::new(
                     1,
-                    format!("Incorrect sample type. Must be Word"),
+                    "Incorrect sample type. Must be Word".to_owned(),
                     Some(sample.clone()),
                 ));
             },
-        }
+        };
+
+        self.sync_tokenizer();
 
         let mut remainder = sample_string;
         let mut tokens: Vec<usize> = Vec::with_capacity(sample_string.len());
 
         // Find and learn new tokens.
-        while remainder.len() > 0 {
+        while !remainder.is_empty() {
             let token_index;
-            if let Some(index) = self.find_next_token(remainder) {
+            if let Some((index, len)) = self.tokenizer().longest_match(remainder) {
                 token_index = index;
-                remainder = &remainder[self.tokens[index].len()..];
+                remainder = &remainder[len..];
             } else {
+                let token = first_grapheme(remainder);
                 token_index = self.tokens.len();
-                self.tokens.push(String::from(&remainder[0..1]));
+                self.tokens.push(token.to_owned());
                 self.max_tokens.push(0);
-                remainder = &remainder[1..];
+                self.sync_tokenizer();
+                remainder = &remainder[token.len()..];
             }
 
             tokens.push(token_index);
         }
-        if tokens.len() < 3 {
+        if tokens.len() <= self.order {
             return Err(LearnError::new(
                 0,
-                format!("3 or more tokens required ({} provided)", tokens.len()),
+                format!("{} or more tokens required ({} provided)", self.order + 1, tokens.len()),
                 Some(sample.clone()),
             ));
         }
@@ -264,16 +512,19 @@ impl Markov {
         }
 
         // Learn start
-        let start_tokens = (tokens[0], tokens[1]);
+        self.ensure_index();
+        let index = self.index.get_mut().unwrap();
+        let start_tokens = &tokens[..self.order];
         let start_length = if self.lrs { tokens.len() } else { 0 };
         let start_index;
-        if let Some((i, start)) = self.starts.iter_mut().enumerate().find(|(_, s)| s.tokens == start_tokens && s.length == start_length) {
+        if let Some(i) = index.starts.get(&(start_tokens.to_vec(), start_length)).cloned() {
             start_index = i;
-            start.weight += 1;
+            self.starts[i].weight += 1;
         } else {
             start_index = self.starts.len();
+            index.starts.insert((start_tokens.to_vec(), start_length), start_index);
             self.starts.push(StartNode{
-                tokens: start_tokens,
+                tokens: start_tokens.to_vec(),
                 weight: 1,
                 length: if self.lrs { tokens.len() } else { 0 },
                 children: Vec::with_capacity(8),
@@ -282,7 +533,7 @@ impl Markov {
         self.total_starts += 1;
 
         // Learn length
-        let length_index = tokens.len() - 3;
+        let length_index = tokens.len() - self.order - 1;
         while self.lengths.len() <= length_index {
             self.lengths.push(0);
         }
@@ -290,29 +541,38 @@ impl Markov {
         self.total_lengths += 1;
 
         // Learn rest of name.
-        let mut prev = start_tokens;
+        let mut prev = start_tokens.to_vec();
         let length_m = if self.lrm { tokens.len() } else { 0 };
         let length_e = if self.lre { tokens.len() } else { 0 };
-        for (i, token) in tokens.iter().cloned().enumerate().skip(2) {
+        for (i, token) in tokens.iter().cloned().enumerate().skip(self.order) {
             let ending = i == (tokens.len() - 1);
             let length = if ending { length_e } else { length_m };
             let current_index;
 
-            if let Some(node_index) = Node::find_current(&self.nodes, prev, token, length, ending) {
+            if let Some(node_index) = index.find_node(&prev, token, length, ending) {
                 current_index = node_index;
+                self.nodes[node_index].count += 1;
             } else {
                 current_index = self.nodes.len();
                 self.nodes.push(Node{
-                    prev, token, length, ending,
+                    prev: prev.clone(),
+                    token, length, ending,
                     children: Vec::with_capacity(8),
                     weight: 1,
-                })
+                    count: 1,
+                });
+                index.add_node(&self.nodes[current_index], current_index);
             }
 
-            if i > 2 {
-                for (_, node) in Node::list_prev(&mut self.nodes, prev, length_m) {
-                    if !node.children.contains(&current_index) {
-                        node.children.push(current_index);
+            if i > self.order {
+                // Every node that leads into this context gets the current one as a child.
+                if let Some(prev_indices) = index.next_contexts.get(&(prev.clone(), length_m)) {
+                    for prev_index in prev_indices.iter().cloned() {
+                        let node = &mut self.nodes[prev_index];
+                        if !node.children.contains(&current_index) {
+                            node.children.push(current_index);
+                            index.parents[current_index].push(prev_index);
+                        }
                     }
                 }
             } else {
@@ -321,48 +581,219 @@ impl Markov {
                 }
             }
 
-            prev = (prev.1, token);
+            prev.remove(0);
+            prev.push(token);
         }
 
         Ok(())
     }
 
-    pub fn recalculate_weights(&mut self) {
-        let mut round: Vec<usize> = Vec::with_capacity(64);
-        let mut next_round: HashSet<usize> = HashSet::new();
-        let mut explored: HashSet<usize> = HashSet::new();
+    /// Forget the samples, taking them out of the start and length counts and removing the nodes
+    /// that no other sample passes through. The token frequency restriction is left as it was,
+    /// since it cannot be worked out without the remaining samples. The former state is restored
+    /// if one of the samples was never learned.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let old_state = self.clone();
+        let mut removed: HashSet<usize> = HashSet::new();
+        for sample in sample_set.samples() {
+            if let Err(err) = self.unlearn_norecalc(sample, &mut removed) {
+                *self = old_state;
+                return Err(err);
+            }
+        }
 
-        for i in 0..self.nodes.len() {
-            if !self.nodes[i].ending {
+        self.prune(removed);
+        self.recalculate_weights();
+
+        Ok(())
+    }
+
+    fn unlearn_norecalc(&mut self, sample: &Sample, removed: &mut HashSet<usize>) -> Result<(), LearnError> {
+        let sample_string: &str = match sample {
+            Sample::Word(s) => s,
+            Sample::WordWeighted(s, _) => s,
+            _ => {
+                return Err(LearnError::new(
+                    1,
+                    "Incorrect sample type. Must be Word".to_owned(),
+                    Some(sample.clone()),
+                ));
+            },
+        };
+        let not_learned = || LearnError::new(5, "Sample has not been learned".to_owned(), Some(sample.clone()));
+
+        self.sync_tokenizer();
+
+        let mut remainder = sample_string;
+        let mut tokens: Vec<usize> = Vec::with_capacity(sample_string.len());
+        while !remainder.is_empty() {
+            let (index, len) = self.tokenizer().longest_match(remainder).ok_or_else(not_learned)?;
+            remainder = &remainder[len..];
+            tokens.push(index);
+        }
+        if tokens.len() <= self.order || self.lengths.get(tokens.len() - self.order - 1).cloned().unwrap_or(0) == 0 {
+            return Err(not_learned());
+        }
+
+        let start_length = if self.lrs { tokens.len() } else { 0 };
+        let start_index = self.starts.iter()
+            .position(|s| s.tokens[..] == tokens[..self.order] && s.length == start_length && s.weight > 0)
+            .ok_or_else(not_learned)?;
+
+        let mut node_indices: Vec<usize> = Vec::with_capacity(tokens.len());
+        let length_m = if self.lrm { tokens.len() } else { 0 };
+        let length_e = if self.lre { tokens.len() } else { 0 };
+        for i in self.order..tokens.len() {
+            let ending = i == (tokens.len() - 1);
+            let length = if ending { length_e } else { length_m };
+
+            let node_index = self.index().find_node(&tokens[i - self.order..i], tokens[i], length, ending)
+                .filter(|i| !removed.contains(i))
+                .ok_or_else(not_learned)?;
+            node_indices.push(node_index);
+        }
+
+        self.starts[start_index].weight -= 1;
+        self.total_starts -= 1;
+        self.lengths[tokens.len() - self.order - 1] -= 1;
+        self.total_lengths -= 1;
+
+        // Nodes from before the counts were kept have a count of zero, and are left alone.
+        for node_index in node_indices {
+            let node = &mut self.nodes[node_index];
+            if node.count == 1 {
+                removed.insert(node_index);
+            }
+            node.count = node.count.saturating_sub(1);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the nodes, along with starts that have no weight left, nodes that can no longer
+    /// reach an ending and nodes that can no longer be reached.
+    fn prune(&mut self, mut removed: HashSet<usize>) {
+        self.starts.retain(|s| s.weight > 0);
+
+        loop {
+            let dead_ends: Vec<usize> = (0..self.nodes.len())
+                .filter(|i| !removed.contains(i))
+                .filter(|i| !self.nodes[*i].ending && self.nodes[*i].children.iter().all(|c| removed.contains(c)))
+                .collect();
+            if dead_ends.is_empty() {
+                break;
+            }
+
+            removed.extend(dead_ends);
+        }
+
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = self.starts.iter().flat_map(|s| s.children.iter().cloned()).collect();
+        while let Some(index) = stack.pop() {
+            if reachable[index] || removed.contains(&index) {
                 continue;
             }
 
+            reachable[index] = true;
+            stack.extend(self.nodes[index].children.iter().cloned());
+        }
+
+        let mut remap: Vec<Option<usize>> = Vec::with_capacity(self.nodes.len());
+        let mut next_index = 0;
+        for is_reachable in reachable.iter() {
+            if *is_reachable {
+                remap.push(Some(next_index));
+                next_index += 1;
+            } else {
+                remap.push(None);
+            }
+        }
+
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.nodes = old_nodes.into_iter().zip(reachable.iter()).filter(|(_, r)| **r).map(|(n, _)| n).collect();
+
+        let remap_children = |children: &mut Vec<usize>| {
+            *children = children.iter().filter_map(|c| remap[*c]).collect();
+        };
+        for node in self.nodes.iter_mut() {
+            remap_children(&mut node.children);
+        }
+        for start in self.starts.iter_mut() {
+            remap_children(&mut start.children);
+        }
+
+        self.index = OnceLock::new();
+    }
+
+    pub fn recalculate_weights(&mut self) {
+        self.ensure_index();
+        let index = self.index.get().unwrap();
+
+        let mut round: Vec<usize> = Vec::with_capacity(64);
+        let mut next_round: BTreeSet<usize> = BTreeSet::new();
+        let mut explored = vec![false; self.nodes.len()];
+
+        // Every node starts over from the weight it's created with, so the result doesn't depend
+        // on how many times the weights were calculated before.
+        for i in 0..self.nodes.len() {
             self.nodes[i].weight = 1;
-            next_round.insert(i);
+            if self.nodes[i].ending {
+                next_round.insert(i);
+            }
         }
 
-        while next_round.len() > 0 {
+        // Rounds are processed in index order, since a parent that is in the same round as its
+        // child is explored again in the next round if it comes after the child.
+        while !next_round.is_empty() {
             round.clear();
             round.extend(next_round.iter());
             next_round.clear();
 
             for i in round.iter().cloned() {
-                explored.insert(i);
+                explored[i] = true;
 
-                for j in 0..self.nodes.len() {
-                    if self.nodes[j].has_child(i) {
-                        self.nodes[j].weight += 1;
+                for j in index.parents[i].iter().cloned() {
+                    self.nodes[j].weight += 1;
 
-                        if !explored.contains(&j) {
-                            next_round.insert(j);
-                        }
+                    if !explored[j] {
+                        next_round.insert(j);
                     }
                 }
             }
         }
     }
 
+    fn ensure_index(&mut self) {
+        self.index();
+    }
+
+    /// Get the index, which is built on first use after the generator is deserialized or pruned.
+    fn index(&self) -> &Index {
+        self.index.get_or_init(|| self.build_index())
+    }
+
+    fn build_index(&self) -> Box<Index> {
+        let mut index = Box::new(Index::default());
+        for (i, start) in self.starts.iter().enumerate() {
+            index.starts.entry((start.tokens.clone(), start.length)).or_insert(i);
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            index.add_node(node, i);
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            for child in node.children.iter().cloned() {
+                index.parents[child].push(i);
+            }
+        }
+
+        index
+    }
+
     pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.order == 0 {
+            return Err(ValidationError::new("parts::Markov", "order cannot be zero."))
+        }
+
         let total_length: usize = self.lengths.iter().sum();
         if total_length != self.total_lengths {
             return Err(ValidationError::new("parts::Markov", "total_lengths is not accurate."))
@@ -384,14 +815,16 @@ impl Markov {
                 }
             }
 
-            let (st1, st2) = start.tokens;
-            if st1 >= self.tokens.len() || st2 >= self.tokens.len() {
+            if start.tokens.len() != self.order {
+                return Err(ValidationError::new("parts::Markov", "start tokens do not match the order."))
+            }
+            if start.tokens.iter().any(|t| *t >= self.tokens.len()) {
                 return Err(ValidationError::new("parts::Markov", "start has out of range token."))
             }
         }
 
         for node in self.nodes.iter() {
-            if node.length == 0 && (if node.ending {self.lre} else {self.lrm}) == true {
+            if node.length == 0 && (if node.ending {self.lre} else {self.lrm}) {
                 return Err(ValidationError::new("parts::Markov", "start.length cannot be zero if lrm/lre is true."))
             }
 
@@ -413,16 +846,22 @@ impl Markov {
                 return Err(ValidationError::new("parts::Markov", "ending node cannot have weight <> 1."))
             }
 
-            if node.ending && node.children.len() > 0 {
+            if node.ending && !node.children.is_empty() {
                 return Err(ValidationError::new("parts::Markov", "ending node cannot have children."))
             }
 
-            let (pt1, st2) = node.prev;
-            if pt1 >= self.nodes.len() || st2 >= self.nodes.len() {
+            if node.prev.len() != self.order {
+                return Err(ValidationError::new("parts::Markov", "node prev does not match the order."))
+            }
+            if node.prev.iter().any(|t| *t >= self.tokens.len()) {
                 return Err(ValidationError::new("parts::Markov", "node has out of range prev."))
             }
         }
 
+        if !self.starts.is_empty() && !self.can_generate() {
+            return Err(ValidationError::new("parts::Markov", "no start can reach an ending."))
+        }
+
         Ok(())
     }
 
@@ -434,7 +873,7 @@ impl Markov {
 
     /// Create a new generator with pre-defined tokens and no constraints. The tokens allow you
     /// to define vowel pairs (e.g. ae, ay, ey), digraphs (e.g. th, nth, ng) so that they're treated
-    /// as one token.
+    /// as one token. Where tokens overlap, like `ts` and `tsy`, the longest one that fits is used.
     pub fn with_tokens<S: AsRef<str>>(tokens: &[S]) -> Markov {
         Self::with_constraints(tokens, false, false, false, false)
     }
@@ -443,6 +882,20 @@ impl Markov {
     /// increases the faithfulness of the generator to the sample material, but at the cost of
     /// variety.
     pub fn with_constraints<S: AsRef<str>>(tokens: &[S], lrs: bool, lrm: bool, lre: bool, rtf: bool) -> Markov {
+        Self::with_order(tokens, 2, lrs, lrm, lre, rtf)
+    }
+
+    /// Create a new generator like `with_constraints`, but with a different order than the default
+    /// of 2. The order is how many preceding tokens decide the next one. A low order gives more
+    /// variety from a small set of samples, while a high order stays closer to the samples and
+    /// needs more of them. Samples must be at least `order + 1` tokens long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the order is zero.
+    pub fn with_order<S: AsRef<str>>(tokens: &[S], order: usize, lrs: bool, lrm: bool, lre: bool, rtf: bool) -> Markov {
+        assert!(order > 0, "Markov order cannot be zero");
+
         Markov{
             tokens: tokens.iter().map(|d| d.as_ref().to_owned()).collect(),
             max_tokens: vec![0usize; tokens.len()],
@@ -452,18 +905,45 @@ impl Markov {
 
             lengths: vec![0usize; 8],
             total_lengths: 0,
+            order,
+            creativity: 0.0,
+            index: OnceLock::new(),
+            tokenizer: OnceLock::from(Tokenizer::new(tokens)),
 
             lrs, lrm, lre, rtf,
         }
     }
+
+    /// Set how often generation falls back on a shorter context, from 0 (never) to 1 (every step).
+    /// Falling back lets the chain take turns that no sample has in the full context, which gives
+    /// more novel names at the cost of fidelity. When it's above 0, a chain that dead-ends also
+    /// falls back before giving up. The default is 0.
+    pub fn set_creativity(&mut self, creativity: f64) {
+        self.creativity = creativity.clamp(0.0, 1.0);
+    }
+
+    pub fn creativity(&self) -> f64 {
+        self.creativity
+    }
+
+    /// Get the number of preceding tokens that decide the next one.
+    pub fn order(&self) -> usize {
+        self.order
+    }
 }
 
-#[derive(Clone)]
+impl Default for Markov {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, std::fmt::Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 struct Node {
     #[cfg_attr(feature = "serde", serde(rename="p"))]
-    prev: (usize, usize),
+    prev: Vec<usize>,
     #[cfg_attr(feature = "serde", serde(rename="t"))]
     token: usize,
     #[cfg_attr(feature = "serde", serde(rename="w"))]
@@ -480,32 +960,55 @@ struct Node {
     #[cfg_attr(feature = "serde", serde(rename="e"))]
     #[cfg_attr(feature = "serde", serde(skip_serializing_if="is_false"))]
     ending: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(rename="n"))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="is_zero"))]
+    count: usize,
 }
 
-impl Node {
-    pub fn has_child(&self, index: usize) -> bool {
-        self.children.iter().find(|p| **p == index).is_some()
+/// Lookup tables that keep learning from scanning every node. They are not serialized, and they
+/// are rebuilt from the nodes when needed.
+#[derive(Clone, Default)]
+struct Index {
+    /// (tokens, length) to start.
+    starts: HashMap<(Vec<usize>, usize), usize>,
+    /// (prev, token, length, ending) to node.
+    nodes: HashMap<(Vec<usize>, usize, usize, bool), usize>,
+    /// (context after the node, length) to the non-ending nodes that lead into it.
+    next_contexts: HashMap<(Vec<usize>, usize), Vec<usize>>,
+    /// The nodes that have each node as a child.
+    parents: Vec<Vec<usize>>,
+    /// The last `order - 1` tokens before a node to the nodes, for backing off to a shorter context.
+    backoff: HashMap<Vec<usize>, Vec<usize>>,
+}
+
+impl Index {
+    fn find_node(&self, prev: &[usize], token: usize, length: usize, ending: bool) -> Option<usize> {
+        self.nodes.get(&(prev.to_vec(), token, length, ending)).cloned()
     }
 
-    fn list_prev(list: &mut [Node], prev: (usize, usize), length: usize) -> impl Iterator<Item=(usize, &mut Node)> {
-        list.iter_mut().enumerate().filter(move |(_, n)| n.length == length && n.prev.1 == prev.0 && n.token == prev.1 && n.ending == false)
+    fn backoff_candidates<'a>(&'a self, context: &[usize]) -> impl Iterator<Item=usize> + Clone + 'a {
+        self.backoff.get(context).into_iter().flatten().cloned()
     }
 
-    fn find_current(list: &[Node], prev: (usize, usize), current: usize, length: usize, ending: bool) -> Option<usize> {
-        if length > 0 {
-            list.iter().enumerate().filter(|(_, n)| n.length == length && n.ending == ending && n.prev == prev && n.token == current).map(|(i, _)| i).next()        
-        } else {
-            list.iter().enumerate().filter(|(_, n)| n.ending == ending && n.prev == prev && n.token == current).map(|(i, _)| i).next()                    
+    fn add_node(&mut self, node: &Node, index: usize) {
+        self.nodes.entry((node.prev.clone(), node.token, node.length, node.ending)).or_insert(index);
+        self.backoff.entry(node.prev[1..].to_vec()).or_default().push(index);
+        if !node.ending {
+            let mut next_context = node.prev[1..].to_vec();
+            next_context.push(node.token);
+            self.next_contexts.entry((next_context, node.length)).or_default().push(index);
         }
+        self.parents.push(Vec::new());
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, std::fmt::Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 struct StartNode {
     #[cfg_attr(feature = "serde", serde(rename="t"))]
-    tokens: (usize, usize),
+    tokens: Vec<usize>,
     #[cfg_attr(feature = "serde", serde(rename="w"))]
     weight: usize,
     #[cfg_attr(feature = "serde", serde(default))]
@@ -516,14 +1019,480 @@ struct StartNode {
     children: Vec<usize>,
 }
 
+/// This is only used for deserialize
+#[allow(dead_code)]
+fn default_order() -> usize {
+    2
+}
+
 /// This is only used for serialize
 #[allow(clippy::trivially_copy_pass_by_ref, dead_code)]
 fn is_false(v: &bool) -> bool {
     !*v
 }
 
+/// This is only used for serialize
+#[allow(clippy::trivially_copy_pass_by_ref, dead_code)]
+fn is_zero_f64(v: &f64) -> bool {
+    *v == 0.0
+}
+
 /// This is only used for serialize
 #[allow(clippy::trivially_copy_pass_by_ref, dead_code)]
 fn is_zero(v: &usize) -> bool {
     *v == 0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+    use crate::sample::sample_set;
+
+    #[test]
+    fn test_score() {
+        let mut markov = Markov::new();
+        markov.learn(&sample_set(&["abc", "abd", "abcd"])).unwrap();
+
+        assert_close(markov.score("abc"), 2.0 / 3.0 * 0.5);
+        assert_close(markov.score("abd"), 2.0 / 3.0 * 0.5);
+        assert_close(markov.score("abcd"), 1.0 / 3.0);
+        assert_eq!(markov.score("abe"), None);
+        assert_eq!(markov.score("bcd"), None);
+        assert_eq!(markov.score("ab"), None);
+        assert_eq!(markov.score("abdc"), None);
+    }
+
+    #[test]
+    fn test_score_constraints() {
+        let mut markov = Markov::with_constraints(&[] as &[&str], true, false, true, true);
+        markov.learn(&sample_set(&["abab", "abc"])).unwrap();
+
+        assert_close(markov.score("abab"), 0.5);
+        assert_close(markov.score("abc"), 0.5);
+        assert_eq!(markov.score("ababab"), None);
+    }
+
+    #[test]
+    fn test_longest_token() {
+        let mut markov = Markov::with_tokens(&["t", "ts", "tsy", "th"]);
+        markov.learn(&sample_set(&["atsya", "thora", "tyra"])).unwrap();
+
+        let token_strs: Vec<&str> = markov.starts.iter()
+            .map(|s| markov.tokens[*s.tokens.last().unwrap()].as_str())
+            .collect();
+        assert_eq!(token_strs, vec!["tsy", "o", "y"]);
+        assert!(markov.score("atsya").is_some());
+        assert!(markov.score("athora").is_none());
+
+        // A copy without the tokenizer, like a deserialized one, builds it once and keeps it.
+        let mut copy = markov.clone();
+        copy.tokenizer = OnceLock::new();
+        assert_eq!(copy.score("atsya"), markov.score("atsya"));
+        assert!(copy.tokenizer.get().is_some());
+        copy.learn(&sample_set(&["tsyra"])).unwrap();
+        assert!(copy.score("tsyra").is_some());
+    }
+
+    #[test]
+    fn test_unicode() {
+        let nordic = ["bjørn", "øyvind", "åsa", "sigrún", "þórr", "ragnhild", "ásgeir", "hrafnhildur", "søren", "æsa"];
+        let slavic = ["владимир", "светлана", "ярослав", "людмила", "dušan", "željko", "milošević", "józef", "świętosław"];
+        let japanese = ["tarō", "shōta", "yūki", "kyōko", "ryūnosuke", "kōtarō", "yūko", "shūhei"];
+
+        for (i, corpus) in [&nordic[..], &slavic[..], &japanese[..]].iter().enumerate() {
+            let mut markov = Markov::with_tokens(&["sh", "ky", "ry", "ō"]);
+            markov.learn(&sample_set(corpus)).unwrap();
+            markov.validate().unwrap();
+
+            for word in corpus.iter() {
+                assert!(markov.score(word).unwrap() > 0.0, "{}", word);
+            }
+            for name in generate_many(&markov, i as u64, 50) {
+                assert!(!name.is_empty());
+                assert!(name.chars().all(|c| corpus.iter().any(|w| w.contains(c))), "{}", name);
+            }
+        }
+
+        // Decomposed diacritics stay with their letter, even when the bare letter is a token.
+        let mut markov = Markov::with_tokens(&["o"]);
+        markov.learn(&sample_set(&["to\u{304}ru", "tomo", "ko\u{304}ta"])).unwrap();
+        assert!(markov.tokens.iter().any(|t| t == "o\u{304}"));
+        assert!(markov.tokens.iter().all(|t| !t.starts_with('\u{304}')));
+    }
+
+    #[test]
+    fn test_order() {
+        let words = ["aeris", "baldur", "celia", "dorian", "elena", "faelar", "gorim", "hestia"];
+
+        for order in 1..=4 {
+            let mut markov = Markov::with_order(&[] as &[&str], order, false, false, false, false);
+            markov.learn(&sample_set(&words)).unwrap();
+            markov.validate().unwrap();
+            assert_eq!(markov.order(), order);
+
+            let mut ws = WorkingSet::new();
+            let mut rng = SmallRng::seed_from_u64(order as u64);
+            for _ in 0..50 {
+                markov.generate(&mut ws, &mut rng);
+                assert!(ws.result_str.chars().count() > order, "{}", ws.result_str);
+                assert!(markov.score(&ws.result_str).is_some(), "{}", ws.result_str);
+            }
+        }
+
+        // With an order of 4, every five-letter chain is one of the samples.
+        let mut markov = Markov::with_order(&[] as &[&str], 4, false, false, false, false);
+        markov.learn(&sample_set(&words)).unwrap();
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(4);
+        for _ in 0..50 {
+            markov.generate(&mut ws, &mut rng);
+            assert!(words.contains(&ws.result_str.as_str()), "{}", ws.result_str);
+        }
+
+        let err = markov.learn_one(&Sample::Word("abcd".to_owned())).unwrap_err();
+        assert!(err.to_string().contains("5 or more tokens"));
+    }
+
+    #[test]
+    fn test_order_one() {
+        let mut markov = Markov::with_order(&[] as &[&str], 1, false, false, false, false);
+        markov.learn(&sample_set(&["ab", "abab", "bba"])).unwrap();
+
+        assert!(markov.score("ab").is_some());
+        assert!(markov.score("abab").is_some());
+        assert!(markov.score("aba").is_some());
+        assert_eq!(markov.score("a"), None);
+        assert_eq!(markov.score("bab"), None);
+    }
+
+    #[test]
+    fn test_creativity() {
+        let words = ["aeris", "baldur", "celia", "dorian", "elena", "faelar", "gorim", "hestia"];
+        let mut markov = Markov::with_constraints(&[] as &[&str], true, false, true, false);
+        markov.learn(&sample_set(&words)).unwrap();
+
+        let novel = |results: &[String]| -> HashSet<String> {
+            results.iter().filter(|w| !words.contains(&w.as_str())).cloned().collect()
+        };
+
+        let strict = generate_many(&markov, 1, 200);
+        markov.set_creativity(0.5);
+        let creative = generate_many(&markov, 1, 200);
+        assert!(novel(&creative).len() > novel(&strict).len() * 3, "{:?} {:?}", novel(&strict), novel(&creative));
+        for word in creative.iter() {
+            assert!(markov.score(word).is_some(), "{}", word);
+        }
+
+        // A copy without the index, like a deserialized one, builds it to back off.
+        let mut copy = markov.clone();
+        copy.index = OnceLock::new();
+        assert_eq!(generate_many(&copy, 1, 200), creative);
+
+        markov.set_creativity(3.0);
+        assert_eq!(markov.creativity(), 1.0);
+        markov.set_creativity(0.0);
+        assert_eq!(generate_many(&markov, 1, 200), strict);
+    }
+
+    #[test]
+    fn test_creativity_score() {
+        let mut markov = Markov::new();
+        markov.learn(&sample_set(&["abc", "dbe"])).unwrap();
+        assert_eq!(markov.score("abe"), None);
+
+        markov.set_creativity(0.5);
+        // a, b is followed by c, but b alone is followed by c or e.
+        assert_close(markov.score("abe"), 0.5 * 0.5 * 0.5);
+        assert_close(markov.score("abc"), 0.5 * (0.5 + 0.5 * 0.5));
+    }
+
+    #[test]
+    fn test_try_generate() {
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(7);
+
+        let mut markov = Markov::with_constraints(&[] as &[&str], true, true, true, true);
+        assert!(!markov.can_generate());
+        assert!(markov.try_generate(&mut ws, &mut rng, 10).is_err());
+
+        markov.learn(&sample_set(&["aeris", "baldur"])).unwrap();
+        assert!(markov.can_generate());
+        assert!(markov.try_generate(&mut ws, &mut rng, 0).is_err());
+        markov.try_generate(&mut ws, &mut rng, 10).unwrap();
+        assert!(["aeris", "baldur"].contains(&ws.get_result()), "{}", ws.get_result());
+
+        // Cut the ending off of every chain.
+        for node in markov.nodes.iter_mut() {
+            node.ending = false;
+        }
+        assert!(!markov.can_generate());
+        assert!(markov.try_generate(&mut ws, &mut rng, 10).is_err());
+        markov.generate(&mut ws, &mut rng);
+        assert_eq!(ws.get_result(), "");
+    }
+
+    #[test]
+    fn test_unlearn() {
+        let mut markov = Markov::with_constraints(&[] as &[&str], false, true, true, false);
+        markov.learn(&sample_set(&["abc", "abd", "abcd", "bcd"])).unwrap();
+
+        markov.unlearn(&sample_set(&["abd", "abcd"])).unwrap();
+        markov.validate().unwrap();
+        assert_close(markov.score("abc"), 0.5);
+        assert_close(markov.score("bcd"), 0.5);
+        assert_eq!(markov.score("abd"), None);
+        assert_eq!(markov.score("abcd"), None);
+        assert_eq!(markov.nodes.len(), 2);
+
+        assert!(markov.unlearn(&sample_set(&["bcd", "abd"])).is_err());
+        assert!(markov.unlearn(&sample_set(&["xyz"])).is_err());
+        assert_close(markov.score("bcd"), 0.5);
+
+        markov.unlearn(&sample_set(&["abc", "bcd"])).unwrap();
+        markov.validate().unwrap();
+        assert!(markov.starts.is_empty());
+        assert!(markov.nodes.is_empty());
+        assert!(markov.try_generate(&mut WorkingSet::new(), &mut SmallRng::seed_from_u64(1), 10).is_err());
+    }
+
+    #[test]
+    fn test_unlearn_weights() {
+        let first = ["aeris", "aerin", "baldur", "balder"];
+        let second = ["aerion", "baldric", "celia"];
+
+        let mut markov = Markov::new();
+        markov.learn(&sample_set(&first)).unwrap();
+        let expected = markov.clone();
+
+        markov.learn(&sample_set(&second)).unwrap();
+        markov.unlearn(&sample_set(&second)).unwrap();
+
+        let weights = |m: &Markov| m.nodes.iter().map(|n| n.weight).collect::<Vec<_>>();
+        assert_eq!(weights(&markov), weights(&expected));
+        assert_eq!(markov.nodes, expected.nodes);
+        assert_eq!(generate_many(&markov, 3, 50), generate_many(&expected, 3, 50));
+    }
+
+    #[test]
+    fn test_unlearn_shared() {
+        let mut markov = Markov::new();
+        markov.learn(&sample_set(&["aeris", "aerin", "aerin"])).unwrap();
+
+        markov.unlearn(&sample_set(&["aerin"])).unwrap();
+        markov.validate().unwrap();
+        assert!(markov.score("aerin").is_some());
+
+        markov.unlearn(&sample_set(&["aerin"])).unwrap();
+        markov.validate().unwrap();
+        assert_eq!(markov.score("aerin"), None);
+        assert_close(markov.score("aeris"), 1.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_pairs() {
+        let json = r#"{
+            "tokens": ["a", "b", "c"], "maxTokens": [0, 0, 0],
+            "starts": [{"t": [0, 1], "w": 1, "c": [0]}], "totalStarts": 1,
+            "nodes": [{"p": [0, 1], "t": 2, "w": 1, "e": true}],
+            "lengths": [1], "totalLengths": 1,
+            "lrs": false, "lrm": false, "lre": false, "rtf": false
+        }"#;
+        let markov: Markov = serde_json::from_str(json).unwrap();
+
+        markov.validate().unwrap();
+        assert_eq!(markov.order(), 2);
+        assert_eq!(markov.score("abc"), Some(1.0));
+    }
+
+    #[test]
+    fn test_index() {
+        let words = [
+            "aeris", "baldur", "celia", "dorian", "elena", "faelar", "gorim", "hestia", "aerin", "baldric",
+            "celestia", "doran", "elenor", "faenor", "goran", "hestor", "aaaa", "abab", "ababab", "nnnn",
+        ];
+
+        for &(order, lrs, lrm, lre) in [(2, false, false, false), (2, true, true, true), (1, false, true, false), (3, false, false, true)].iter() {
+            let mut markov = Markov::with_order(&["ae"], order, lrs, lrm, lre, true);
+            let mut naive = markov.clone();
+            for chunk in words.chunks(3) {
+                markov.learn(&sample_set(chunk)).unwrap();
+                naive_learn(&mut naive, chunk);
+            }
+
+            assert_eq!(markov.starts, naive.starts);
+            assert_eq!(markov.nodes, naive.nodes);
+            assert_eq!(markov.lengths, naive.lengths);
+            assert_eq!(markov.tokens, naive.tokens);
+
+            // A copy without the index, like a deserialized one, must learn the same way.
+            let mut copy = markov.clone();
+            copy.index = OnceLock::new();
+            copy.learn(&sample_set(&["hestiaris"])).unwrap();
+            markov.learn(&sample_set(&["hestiaris"])).unwrap();
+            assert_eq!(copy.nodes, markov.nodes);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_baseline_model() {
+        // "aeris", "thoran", "baldur" and "celia" learned with the tokens "th" and "ae" by namegen
+        // 0.1.4, before the index and the weight reset.
+        let json = r#"{
+            "tokens": ["th", "ae", "r", "i", "s", "t", "h", "o", "a", "n", "b", "l", "d", "u", "c", "e"],
+            "maxTokens": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            "starts": [
+                {"t": [1, 2], "w": 1, "c": [0]}, {"t": [5, 6], "w": 1, "c": [2]},
+                {"t": [10, 8], "w": 1, "c": [6]}, {"t": [14, 15], "w": 1, "c": [10]}
+            ],
+            "totalStarts": 4,
+            "nodes": [
+                {"p": [1, 2], "t": 3, "w": 2, "c": [1]}, {"p": [2, 3], "t": 4, "w": 1, "e": true},
+                {"p": [5, 6], "t": 7, "w": 2, "c": [3]}, {"p": [6, 7], "t": 2, "w": 2, "c": [4]},
+                {"p": [7, 2], "t": 8, "w": 2, "c": [5]}, {"p": [2, 8], "t": 9, "w": 1, "e": true},
+                {"p": [10, 8], "t": 11, "w": 2, "c": [7]}, {"p": [8, 11], "t": 12, "w": 2, "c": [8]},
+                {"p": [11, 12], "t": 13, "w": 2, "c": [9]}, {"p": [12, 13], "t": 2, "w": 1, "e": true},
+                {"p": [14, 15], "t": 11, "w": 2, "c": [11]}, {"p": [15, 11], "t": 3, "w": 2, "c": [12]},
+                {"p": [11, 3], "t": 8, "w": 1, "e": true}
+            ],
+            "lengths": [0, 1, 1, 2, 0, 0, 0, 0],
+            "totalLengths": 4,
+            "lrs": false, "lrm": false, "lre": false, "rtf": false
+        }"#;
+        let weights = |m: &Markov| m.nodes.iter().map(|n| n.weight).collect::<Vec<_>>();
+
+        let mut markov: Markov = serde_json::from_str(json).unwrap();
+        markov.validate().unwrap();
+        let loaded = weights(&markov);
+        markov.recalculate_weights();
+        assert_eq!(weights(&markov), loaded);
+        let names: BTreeSet<String> = generate_many(&markov, 5, 200).into_iter().collect();
+        assert_eq!(names, ["aeris", "baldur", "celia", "thoran"].iter().map(|s| (*s).to_owned()).collect());
+
+        // The second batch adds children to the "aeri", "thor" and "celi" nodes, whose weights are
+        // calculated over instead of added to.
+        markov.learn(&sample_set(&["thora", "aerin", "celian"])).unwrap();
+        markov.validate().unwrap();
+        assert_eq!(weights(&markov), [3, 1, 2, 3, 2, 1, 2, 2, 2, 1, 2, 3, 1, 2, 1, 1, 2, 1]);
+        let names: BTreeSet<String> = generate_many(&markov, 5, 200).into_iter().collect();
+        let expected = ["aerin", "aeris", "baldur", "celia", "celian", "thora", "thoran"];
+        assert_eq!(names, expected.iter().map(|s| (*s).to_owned()).collect());
+    }
+
+    /// The learning algorithm as it was before the index, scanning the nodes for everything.
+    fn naive_learn(markov: &mut Markov, words: &[&str]) {
+        for word in words.iter() {
+            let mut remainder = *word;
+            let mut tokens = Vec::new();
+            while !remainder.is_empty() {
+                // The first of the longest tokens, like the tokenizer.
+                let longest = markov.tokens.iter().enumerate().rev()
+                    .filter(|(_, t)| remainder.starts_with(t.as_str()))
+                    .max_by_key(|(_, t)| t.len());
+                let index = match longest {
+                    Some((index, _)) => index,
+                    None => {
+                        markov.tokens.push(first_grapheme(remainder).to_owned());
+                        markov.max_tokens.push(0);
+                        markov.tokens.len() - 1
+                    }
+                };
+                remainder = &remainder[markov.tokens[index].len()..];
+                tokens.push(index);
+            }
+
+            for token in tokens.iter() {
+                let count = tokens.iter().filter(|t| *t == token).count();
+                markov.max_tokens[*token] = markov.max_tokens[*token].max(count);
+            }
+
+            let order = markov.order;
+            let start_length = if markov.lrs { tokens.len() } else { 0 };
+            let start_index = match markov.starts.iter().position(|s| s.tokens[..] == tokens[..order] && s.length == start_length) {
+                Some(i) => i,
+                None => {
+                    markov.starts.push(StartNode{tokens: tokens[..order].to_vec(), weight: 0, length: start_length, children: Vec::new()});
+                    markov.starts.len() - 1
+                }
+            };
+            markov.starts[start_index].weight += 1;
+            markov.total_starts += 1;
+            while markov.lengths.len() < tokens.len() - order {
+                markov.lengths.push(0);
+            }
+            markov.lengths[tokens.len() - order - 1] += 1;
+            markov.total_lengths += 1;
+
+            let length_m = if markov.lrm { tokens.len() } else { 0 };
+            let length_e = if markov.lre { tokens.len() } else { 0 };
+            for i in order..tokens.len() {
+                let prev = &tokens[i - order..i];
+                let ending = i == tokens.len() - 1;
+                let length = if ending { length_e } else { length_m };
+
+                let current = match markov.nodes.iter().position(|n| n.prev == prev && n.token == tokens[i] && n.length == length && n.ending == ending) {
+                    Some(current) => {
+                        markov.nodes[current].count += 1;
+                        current
+                    }
+                    None => {
+                        markov.nodes.push(Node{prev: prev.to_vec(), token: tokens[i], length, ending, children: Vec::new(), weight: 1, count: 1});
+                        markov.nodes.len() - 1
+                    }
+                };
+
+                if i > order {
+                    for node in markov.nodes.iter_mut() {
+                        if node.length == length_m && !node.ending && node.prev[1..] == prev[..order - 1]
+                            && node.token == prev[order - 1] && !node.children.contains(&current)
+                        {
+                            node.children.push(current);
+                        }
+                    }
+                } else if !markov.starts[start_index].children.contains(&current) {
+                    markov.starts[start_index].children.push(current);
+                }
+            }
+        }
+
+        let mut next_round: BTreeSet<usize> = (0..markov.nodes.len()).filter(|i| markov.nodes[*i].ending).collect();
+        for node in markov.nodes.iter_mut() {
+            node.weight = 1;
+        }
+        let mut explored: HashSet<usize> = HashSet::new();
+        while !next_round.is_empty() {
+            let round: Vec<usize> = next_round.iter().cloned().collect();
+            next_round.clear();
+
+            for i in round {
+                explored.insert(i);
+                for j in 0..markov.nodes.len() {
+                    if markov.nodes[j].children.contains(&i) {
+                        markov.nodes[j].weight += 1;
+                        if !explored.contains(&j) {
+                            next_round.insert(j);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn generate_many(markov: &Markov, seed: u64, count: usize) -> Vec<String> {
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(seed);
+
+        (0..count).map(|_| {
+            markov.generate(&mut ws, &mut rng);
+            ws.result_str.clone()
+        }).collect()
+    }
+
+    fn assert_close(score: Option<f64>, expected: f64) {
+        let score = score.unwrap();
+        assert!((score - expected).abs() < 1e-9, "{} != {}", score, expected);
+    }
+}
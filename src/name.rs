@@ -3,7 +3,12 @@ use rand::{SeedableRng, thread_rng};
 use rand::rngs::SmallRng;
 use rand::Rng;
 use rand::prelude::ThreadRng;
-use crate::core::ValidationError;
+use crate::core::{ValidationError, GenerateError, SyntaxError};
+use crate::filter::{NameFilter, DEFAULT_MAX_RETRIES};
+use std::collections::{BTreeMap, HashSet};
+
+/// How deep format references can be nested, which stops a format that refers back to itself.
+const MAX_FORMAT_DEPTH: usize = 32;
 
 #[derive(Clone, std::fmt::Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -12,7 +17,10 @@ enum FormatPart {
     Text(String),
     Part(usize),
     Format(usize),
+    FormatRef(String),
     Random(Vec<FormatPart>),
+    Weighted(Vec<(FormatPart, u32)>),
+    Optional(f64, Vec<FormatPart>),
 }
 
 impl FormatPart {
@@ -36,12 +44,32 @@ impl FormatPart {
                     Ok(())
                 }
             },
-            FormatPart::Random(list) => {
+            FormatPart::FormatRef(_) => {
+                Err(
+                    ValidationError::new("ngen::NameFormat", "Name format references a format that is not defined.")
+                )
+            },
+            FormatPart::Optional(probability, _) if !(0.0..=1.0).contains(probability) => {
+                Err(
+                    ValidationError::new("ngen::NameFormat", "Name format has an invalid probability.")
+                )
+            },
+            FormatPart::Random(list) | FormatPart::Optional(_, list) => {
                 for item in list.iter() {
-                    let res = item.validate_against(name);
-                    if res.is_err() {
-                        return res
-                    }
+                    item.validate_against(name)?;
+                }
+
+                Ok(())
+            },
+            FormatPart::Weighted(list) => {
+                if list.iter().any(|(_, weight)| *weight == 0) {
+                    return Err(ValidationError::new("ngen::NameFormat", "Name format has an alternative without weight."));
+                }
+                if list.iter().try_fold(0u32, |total, (_, weight)| total.checked_add(*weight)).is_none() {
+                    return Err(ValidationError::new("ngen::NameFormat", "Name format has weights that add up to too much."));
+                }
+                for (item, _) in list.iter() {
+                    item.validate_against(name)?;
                 }
 
                 Ok(())
@@ -49,6 +77,42 @@ impl FormatPart {
             FormatPart::Text(_) => Ok(())
         }
     }
+
+    /// Add the indices of the formats this refers to.
+    fn collect_references(&self, target: &mut Vec<usize>) {
+        match self {
+            FormatPart::Format(index) => target.push(*index),
+            FormatPart::Random(list) | FormatPart::Optional(_, list) => {
+                for item in list.iter() {
+                    item.collect_references(target);
+                }
+            }
+            FormatPart::Weighted(list) => {
+                for (item, _) in list.iter() {
+                    item.collect_references(target);
+                }
+            }
+            FormatPart::Text(_) | FormatPart::Part(_) | FormatPart::FormatRef(_) => {}
+        }
+    }
+
+    /// Replace references to the format name with its index.
+    fn resolve(&mut self, format_name: &str, format_index: usize) {
+        match self {
+            FormatPart::FormatRef(name) if name == format_name => *self = FormatPart::Format(format_index),
+            FormatPart::Random(list) | FormatPart::Optional(_, list) => {
+                for item in list.iter_mut() {
+                    item.resolve(format_name, format_index);
+                }
+            }
+            FormatPart::Weighted(list) => {
+                for (item, _) in list.iter_mut() {
+                    item.resolve(format_name, format_index);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -93,7 +157,178 @@ impl Name {
         )
     }
 
-    pub fn add_format(&mut self, name: &str, str: &str) {
+    /// Make a part forget samples it has learned. See [`NamePart::unlearn`].
+    pub fn unlearn(&mut self, part_name: &str, sample_set: &SampleSet) -> Result<(), LearnError> {
+        match self.parts.iter_mut().find(|p| p.name() == part_name) {
+            Some(part) => part.unlearn(sample_set),
+            None => Err(
+                LearnError::new(
+                    100,
+                    format!("Part {} not found", part_name),
+                    None
+                )
+            ),
+        }
+    }
+
+    /// Add a format, which is text with parts in braces, e.g. `{first} {last}`. The braces can hold
+    /// a part name, a format name after a colon (`{:full}`), or literal text after an equals sign
+    /// (`{=Sir }`), and alternatives separated by `|` are picked at random. An alternative can
+    /// have a weight, e.g. `{first*3|:nickname}`, and a trailing `*` with digits is always read as
+    /// one.
+    ///
+    /// Braces that start with `?` make an optional segment, which is left out with even odds, or
+    /// with the given probability, e.g. `{first}{?30%: {middle}} {last}`. Segments can hold
+    /// anything a format can, including other segments. `{{` and `}}` are literal braces, except
+    /// that a `}` inside a segment always closes it, and square brackets are plain text.
+    ///
+    /// The parts must exist, and the error points at the first problem in the string. Formats can
+    /// be referred to before they're added, and [`Name::validate`] checks that they have been
+    /// and that no format refers back to itself.
+    pub fn add_format(&mut self, name: &str, format: &str) -> Result<(), SyntaxError> {
+        let parts = self.parse_format(format)?;
+        self.push_format(name, parts);
+
+        Ok(())
+    }
+
+    /// Add the format, and point the formats that referred to it by name before it existed at it.
+    fn push_format(&mut self, name: &str, parts: Vec<FormatPart>) {
+        self.formats.push(NameFormat{
+            name: name.to_owned(),
+            parts,
+        });
+
+        let index = self.formats.len() - 1;
+        for format in self.formats.iter_mut() {
+            for part in format.parts.iter_mut() {
+                part.resolve(name, index);
+            }
+        }
+    }
+
+    fn parse_format(&self, format: &str) -> Result<Vec<FormatPart>, SyntaxError> {
+        let mut pos = 0;
+        self.parse_format_sequence(format, &mut pos, None)
+    }
+
+    /// Parse from `pos` until the end of the string, or until the `}` that closes the optional
+    /// segment opened at `open`.
+    fn parse_format_sequence(&self, format: &str, pos: &mut usize, open: Option<usize>) -> Result<Vec<FormatPart>, SyntaxError> {
+        let mut parts: Vec<FormatPart> = Vec::with_capacity(8);
+        let mut text = String::new();
+
+        while let Some(ch) = format[*pos..].chars().next() {
+            let i = *pos;
+            *pos += ch.len_utf8();
+            let escaped = format[*pos..].starts_with(ch);
+
+            match ch {
+                '}' if open.is_some() => {
+                    if !text.is_empty() {
+                        parts.push(FormatPart::Text(text));
+                    }
+                    return Ok(parts);
+                }
+                '{' | '}' if escaped => {
+                    *pos += 1;
+                    text.push(ch);
+                }
+                '}' => return Err(SyntaxError::new(i, "Unmatched '}', write '}}' for a literal one".to_owned())),
+                '{' if format[*pos..].starts_with('?') => {
+                    *pos += 1;
+                    let probability = parse_probability(format, pos)?;
+
+                    if !text.is_empty() {
+                        parts.push(FormatPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(FormatPart::Optional(probability, self.parse_format_sequence(format, pos, Some(i))?));
+                }
+                '{' => {
+                    let inner = &format[i + 1..];
+                    let end = match inner.find(['{', '}']) {
+                        Some(j) if inner[j..].starts_with('}') => i + 1 + j,
+                        Some(j) => return Err(SyntaxError::new(i + 1 + j, "Unexpected '{' inside braces".to_owned())),
+                        None => return Err(SyntaxError::new(i, "Unclosed '{'".to_owned())),
+                    };
+
+                    if !text.is_empty() {
+                        parts.push(FormatPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(self.parse_format_alternatives(&format[i + 1..end], i + 1)?);
+                    *pos = end + 1;
+                }
+                _ => text.push(ch),
+            }
+        }
+
+        if let Some(open) = open {
+            return Err(SyntaxError::new(open, "Unclosed '{?'".to_owned()));
+        }
+        if !text.is_empty() {
+            parts.push(FormatPart::Text(text));
+        }
+
+        Ok(parts)
+    }
+
+    /// Parse the inside of a pair of braces, which starts at `offset` in the format string.
+    fn parse_format_alternatives(&self, s: &str, offset: usize) -> Result<FormatPart, SyntaxError> {
+        let mut subparts = Vec::new();
+        let mut total: u32 = 0;
+
+        let mut token_offset = offset;
+        for raw_token in s.split('|') {
+            let (token, weight, weight_offset) = match raw_token.rfind('*') {
+                Some(star) if star + 1 < raw_token.len() && raw_token[star + 1..].bytes().all(|b| b.is_ascii_digit()) => {
+                    match raw_token[star + 1..].parse::<u32>() {
+                        Ok(weight) if weight > 0 => (&raw_token[..star], weight, token_offset + star + 1),
+                        _ => return Err(SyntaxError::new(token_offset + star + 1, "Expected a weight above zero".to_owned())),
+                    }
+                }
+                _ => (raw_token, 1, token_offset),
+            };
+            total = match total.checked_add(weight) {
+                Some(total) => total,
+                None => return Err(SyntaxError::new(weight_offset, format!("The weights in these braces add up to more than {}", u32::MAX))),
+            };
+
+            subparts.push((if let Some(text) = token.strip_prefix('=') {
+                FormatPart::Text(text.to_owned())
+            } else if let Some(format_name) = token.strip_prefix(':') {
+                if format_name.is_empty() {
+                    return Err(SyntaxError::new(token_offset, "Expected a format name".to_owned()));
+                }
+
+                match self.formats.iter().position(|f| f.name == format_name) {
+                    Some(index) => FormatPart::Format(index),
+                    None => FormatPart::FormatRef(format_name.to_owned()),
+                }
+            } else if token.is_empty() {
+                return Err(SyntaxError::new(token_offset, "Expected a part name".to_owned()));
+            } else {
+                match self.parts.iter().position(|p| p.name() == token) {
+                    Some(index) => FormatPart::Part(index),
+                    None => return Err(SyntaxError::new(token_offset, format!("Unknown part '{}'", token))),
+                }
+            }, weight));
+
+            token_offset += raw_token.len() + 1;
+        }
+
+        if subparts.len() == 1 {
+            Ok(subparts.pop().unwrap().0)
+        } else if subparts.iter().all(|(_, weight)| *weight == 1) {
+            Ok(FormatPart::Random(subparts.into_iter().map(|(fp, _)| fp).collect()))
+        } else {
+            Ok(FormatPart::Weighted(subparts))
+        }
+    }
+
+    /// Add a format like `add_format`, but without errors. Unknown alternatives are left out, braces
+    /// where none of them are known are kept as text, as are unbalanced braces, and there are no
+    /// escapes for braces.
+    pub fn add_format_lenient(&mut self, name: &str, str: &str) {
         let mut parts: Vec<FormatPart> = Vec::with_capacity(8);
         let mut subparts: Vec<FormatPart> = Vec::with_capacity(8);
 
@@ -108,11 +343,10 @@ impl Name {
 
             subparts.clear();
 
-            for token in bs.split("|") {
-                if token.starts_with('=') {
-                    subparts.push(FormatPart::Text(token[1..].to_owned()))
-                } else if token.starts_with(':') {
-                    let format_name = &token[1..];
+            for token in bs.split('|') {
+                if let Some(text) = token.strip_prefix('=') {
+                    subparts.push(FormatPart::Text(text.to_owned()))
+                } else if let Some(format_name) = token.strip_prefix(':') {
                     for (i, format) in self.formats.iter().enumerate() {
                         if format.name == format_name {
                             subparts.push(FormatPart::Format(i));
@@ -139,15 +373,12 @@ impl Name {
             pos += end + 1;
         }
 
-        self.formats.push(NameFormat{
-            name: name.to_owned(),
-            parts,
-        })
+        self.push_format(name, parts);
     }
 
     /// Generate names with a fast RNG (SmallRng). This uses `thread_rng()` to
     /// seed, and may return none.
-    pub fn generate(&self, format_name: &str) -> Option<GeneratorIter<SmallRng>> {
+    pub fn generate(&self, format_name: &str) -> Option<GeneratorIter<'_, SmallRng>> {
         if let Ok(rng) = SmallRng::from_rng(thread_rng()) {
             self.generate_with_rng(rng, false, format_name)
         } else {
@@ -157,7 +388,7 @@ impl Name {
 
     /// Same as generate, but selecing a single part instead of using a format. You could
     /// always use NamePart directly, but then you wouldn't get the same iterator syntax.
-    pub fn generate_part(&self, part_name: &str) -> Option<GeneratorIter<SmallRng>> {
+    pub fn generate_part(&self, part_name: &str) -> Option<GeneratorIter<'_, SmallRng>> {
         if let Ok(rng) = SmallRng::from_rng(thread_rng()) {
             self.generate_with_rng(rng, true, part_name)
         } else {
@@ -182,33 +413,33 @@ impl Name {
 
     /// Check if the generator has the requested part name.
     pub fn has_part_name(&self, name: &str) -> bool {
-        self.parts.iter().find(|p| p.name() == name).is_some()
+        self.parts.iter().any(|p| p.name() == name)
     }
 
     /// Check if the generator has the requested format name.
     pub fn has_format_name(&self, name: &str) -> bool {
-        self.formats.iter().find(|f| &f.name == name).is_some()
+        self.formats.iter().any(|f| f.name == name)
     }
 
     /// Generate names with a fast RNG (SmallRng) using a seed. This is useful if your rand
     /// version differs and you want it to be dependent on external reproducable random data
     /// (e.g. if namegen is part of a bigger procedural generation pipeline).
-    pub fn generate_seeded(&self, seed: u64, format_name: &str) -> Option<GeneratorIter<SmallRng>> {
+    pub fn generate_seeded(&self, seed: u64, format_name: &str) -> Option<GeneratorIter<'_, SmallRng>> {
         self.generate_with_rng(SmallRng::seed_from_u64(seed), false, format_name)
     }
 
     /// Generate a name part with a fast RNG (SmallRng) using a seed.
-    pub fn generate_part_seeded(&self, seed: u64, part_name: &str) -> Option<GeneratorIter<SmallRng>> {
+    pub fn generate_part_seeded(&self, seed: u64, part_name: &str) -> Option<GeneratorIter<'_, SmallRng>> {
         self.generate_with_rng(SmallRng::seed_from_u64(seed), true, part_name)
     }
 
     /// If you for some reason need a secure random generator....
-    pub fn generate_with_thread_rng(&self, format_name: &str) -> Option<GeneratorIter<ThreadRng>> {
+    pub fn generate_with_thread_rng(&self, format_name: &str) -> Option<GeneratorIter<'_, ThreadRng>> {
         self.generate_with_rng(thread_rng(), false, format_name)
     }
 
     /// If you for some reason need a secure random generator....
-    pub fn generat_parte_with_thread_rng(&self, part_name: &str) -> Option<GeneratorIter<ThreadRng>> {
+    pub fn generat_parte_with_thread_rng(&self, part_name: &str) -> Option<GeneratorIter<'_, ThreadRng>> {
         self.generate_with_rng(thread_rng(), true, part_name)
     }
 
@@ -221,19 +452,166 @@ impl Name {
             return Err(err)
         }
 
-        for format in self.formats.iter() {
+        self.validate_formats().map_err(|(_, err)| err)
+    }
+
+    /// Validate the formats, and return the index of the format with the problem on error.
+    pub(crate) fn validate_formats(&self) -> Result<(), (usize, ValidationError)> {
+        for (index, format) in self.formats.iter().enumerate() {
             for part in format.parts.iter() {
-                let res = part.validate_against(self).map_err(|e| e.with_name(&format.name));
-                if res.is_err() {
-                    return res;
-                }
+                part.validate_against(self).map_err(|e| (index, e.with_name(&format.name)))?;
             }
         }
 
+        if let Some(index) = self.find_format_cycle() {
+            return Err((
+                index,
+                ValidationError::new("ngen::NameFormat", "Name format refers back to itself.")
+                    .with_name(&self.formats[index].name),
+            ));
+        }
+
         Ok(())
     }
 
-    fn generate_with_rng<T>(&self, rng: T, is_part: bool, name: &str) -> Option<GeneratorIter<T>> where T: Rng {
+    /// Find a format that is part of a reference cycle, with a depth-first search.
+    fn find_format_cycle(&self) -> Option<usize> {
+        // 0 is unvisited, 1 is on the current path and 2 is done.
+        let mut state = vec![0u8; self.formats.len()];
+        let mut references = Vec::new();
+
+        for start in 0..self.formats.len() {
+            if state[start] != 0 {
+                continue;
+            }
+
+            let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+            state[start] = 1;
+            references.clear();
+            self.formats[start].parts.iter().for_each(|p| p.collect_references(&mut references));
+            stack.push((start, references.clone()));
+
+            while let Some((index, pending)) = stack.last_mut() {
+                match pending.pop() {
+                    Some(next) if state[next] == 1 => return Some(next),
+                    Some(next) if state[next] == 0 => {
+                        state[next] = 1;
+                        references.clear();
+                        self.formats[next].parts.iter().for_each(|p| p.collect_references(&mut references));
+                        stack.push((next, references.clone()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        state[*index] = 2;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Get the probability that the format generates `input`, or `None` if it can't. Every way of
+    /// splitting the input between the format's parts counts towards the total, and the parts are
+    /// scored with [`NamePart::score`].
+    pub fn score_format(&self, format_name: &str, input: &str) -> Option<f64> {
+        let format = self.formats.iter().find(|f| f.name == format_name)?;
+
+        match self.score_sequence(&format.parts, input, 0).get(&input.len()) {
+            Some(score) if *score > 0.0 => Some(*score),
+            _ => None,
+        }
+    }
+
+    /// Get the score of the format that fits `input` best, or `None` if no format can generate it.
+    pub fn score(&self, input: &str) -> Option<f64> {
+        self.formats.iter()
+            .filter_map(|f| self.score_format(&f.name, input))
+            .fold(None, |best, score| Some(best.map_or(score, |b: f64| b.max(score))))
+    }
+
+    /// Find every prefix of `input` the format parts can generate in sequence, mapped from its
+    /// length to its probability.
+    fn score_sequence(&self, parts: &[FormatPart], input: &str, depth: usize) -> BTreeMap<usize, f64> {
+        let mut states = BTreeMap::new();
+        states.insert(0usize, 1.0f64);
+
+        for fp in parts.iter() {
+            let mut next_states = BTreeMap::new();
+            for (pos, score) in states.iter() {
+                for (len, part_score) in self.score_prefixes(fp, &input[*pos..], depth) {
+                    *next_states.entry(pos + len).or_insert(0.0) += score * part_score;
+                }
+            }
+
+            if next_states.is_empty() {
+                return next_states;
+            }
+            states = next_states;
+        }
+
+        states
+    }
+
+    fn score_prefixes(&self, fp: &FormatPart, input: &str, depth: usize) -> Vec<(usize, f64)> {
+        match fp {
+            FormatPart::Text(text) => {
+                if input.starts_with(text.as_str()) {
+                    vec![(text.len(), 1.0)]
+                } else {
+                    Vec::new()
+                }
+            }
+            FormatPart::Part(index) => {
+                let part = &self.parts[*index];
+
+                input.char_indices().skip(1).map(|(i, _)| i).chain(std::iter::once(input.len()))
+                    .filter(|len| *len > 0)
+                    .filter_map(|len| part.score(&input[..len]).map(|score| (len, score)))
+                    .collect()
+            }
+            FormatPart::Format(index) if depth < MAX_FORMAT_DEPTH => {
+                self.score_sequence(&self.formats[*index].parts, input, depth + 1).into_iter().collect()
+            }
+            FormatPart::Format(_) | FormatPart::FormatRef(_) => Vec::new(),
+            FormatPart::Random(list) => {
+                let mut res: Vec<(usize, f64)> = Vec::new();
+                for item in list.iter() {
+                    for (len, score) in self.score_prefixes(item, input, depth) {
+                        res.push((len, score / list.len() as f64));
+                    }
+                }
+
+                res
+            }
+            FormatPart::Weighted(list) => {
+                let total: u32 = list.iter().map(|(_, weight)| *weight).sum();
+
+                let mut res: Vec<(usize, f64)> = Vec::new();
+                for (item, weight) in list.iter() {
+                    for (len, score) in self.score_prefixes(item, input, depth) {
+                        res.push((len, score * *weight as f64 / total as f64));
+                    }
+                }
+
+                res
+            }
+            FormatPart::Optional(probability, list) => {
+                let mut res: Vec<(usize, f64)> = Vec::new();
+                if *probability < 1.0 {
+                    res.push((0, 1.0 - probability));
+                }
+                if *probability > 0.0 {
+                    res.extend(self.score_sequence(list, input, depth).into_iter().map(|(len, score)| (len, score * probability)));
+                }
+
+                res
+            }
+        }
+    }
+
+    fn generate_with_rng<T>(&self, rng: T, is_part: bool, name: &str) -> Option<GeneratorIter<'_, T>> where T: Rng {
         if is_part {
             for (i, part) in self.parts.iter().enumerate() {
                 if part.name() == name {
@@ -243,6 +621,12 @@ impl Name {
                             is_part: true,
                             index: i,
                             ws: WorkingSet::new(),
+                            filter: None,
+                            seen: None,
+                            exclude_samples: false,
+                            rejections: 0,
+                            exhausted: false,
+                            error: None,
 
                             rng,
                         }
@@ -258,6 +642,12 @@ impl Name {
                             is_part: false,
                             index: i,
                             ws: WorkingSet::new(),
+                            filter: None,
+                            seen: None,
+                            exclude_samples: false,
+                            rejections: 0,
+                            exhausted: false,
+                            error: None,
 
                             rng,
                         }
@@ -269,31 +659,77 @@ impl Name {
         None
     }
 
-    fn run_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, format_index: usize) {
-        for mut fp in self.formats[format_index].parts.iter() {
-            while let FormatPart::Random(list) = fp {
-                fp = &list[rng.gen_range(0, list.len())];
+    /// Generate into `ws.result_total`, and return false if a part generated one of its samples
+    /// while `exclude_samples` is set. An error names the part that failed and the innermost
+    /// format it was in.
+    fn run_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, format_index: usize, exclude_samples: bool, depth: usize) -> Result<bool, GenerateError> {
+        let format = &self.formats[format_index];
+        if depth >= MAX_FORMAT_DEPTH {
+            return Err(GenerateError::new("ngen::NameFormat", "Format references are nested too deeply.").with_format(&format.name));
+        }
+
+        self.run_generate_parts(ws, rng, &format.parts, &format.name, exclude_samples, depth)
+    }
+
+    fn run_generate_parts(&self, ws: &mut WorkingSet, rng: &mut impl Rng, parts: &[FormatPart], format_name: &str, exclude_samples: bool, depth: usize) -> Result<bool, GenerateError> {
+        for mut fp in parts.iter() {
+            loop {
+                fp = match fp {
+                    FormatPart::Random(list) => &list[rng.gen_range(0, list.len())],
+                    FormatPart::Weighted(list) => {
+                        let total: u32 = list.iter().map(|(_, weight)| *weight).sum();
+                        let mut roll = rng.gen_range(0, total);
+                        let mut picked = &list[0].0;
+                        for (item, weight) in list.iter() {
+                            if roll < *weight {
+                                picked = item;
+                                break;
+                            }
+                            roll -= *weight;
+                        }
+
+                        picked
+                    }
+                    _ => break,
+                };
             }
 
             match fp {
                 FormatPart::Text(text) => {
-                    ws.result_total.push_str(&text);
+                    ws.result_total.push_str(text);
                 }
                 FormatPart::Part(part_index) => {
-                    self.parts[*part_index].generate(ws, rng);
+                    let accepted = self.parts[*part_index].generate_checked(ws, rng, exclude_samples)
+                        .map_err(|e| e.with_format(format_name))?;
+                    if !accepted {
+                        return Ok(false);
+                    }
                     ws.result_total.push_str(&ws.result_str);
                 }
-                FormatPart::Format(format_index) => {
-                    self.run_generate(ws, rng, *format_index);
+                FormatPart::Format(format_index) if !self.run_generate(ws, rng, *format_index, exclude_samples, depth + 1)? => {
+                    return Ok(false);
+                }
+                FormatPart::FormatRef(_) => {
+                    return Err(GenerateError::new("ngen::NameFormat", "Format reference is not defined.").with_format(format_name));
+                }
+                FormatPart::Optional(probability, list) if rng.gen_bool(*probability)
+                    && !self.run_generate_parts(ws, rng, list, format_name, exclude_samples, depth)? => {
+                    return Ok(false);
                 }
                 _ => {}
             }
         }
+
+        Ok(true)
     }
 
-    fn run_generate_part(&self, ws: &mut WorkingSet, rng: &mut impl Rng, part_index: usize) {
-        self.parts[part_index].generate(ws, rng);
+    fn run_generate_part(&self, ws: &mut WorkingSet, rng: &mut impl Rng, part_index: usize, exclude_samples: bool) -> Result<bool, GenerateError> {
+        if !self.parts[part_index].generate_checked(ws, rng, exclude_samples)? {
+            return Ok(false);
+        }
         ws.result_total.push_str(&ws.result_str);
+
+        Ok(true)
     }
 
     pub fn new() -> Name {
@@ -304,26 +740,142 @@ impl Name {
     }
 }
 
+impl Default for Name {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct GeneratorIter<'a, T> where T: Rng {
     name: &'a Name,
     rng: T,
     is_part: bool,
     index: usize,
     ws: WorkingSet,
+    filter: Option<NameFilter>,
+    seen: Option<HashSet<String>>,
+    exclude_samples: bool,
+    rejections: usize,
+    exhausted: bool,
+    error: Option<GenerateError>,
+}
+
+impl<'a, T> GeneratorIter<'a, T> where T: Rng {
+    /// Reject names that do not pass the filter and generate new ones in their place. If the
+    /// filter's retry budget is spent on one name, the iterator ends.
+    pub fn with_filter(mut self, filter: NameFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Never generate the same name twice. The iterator ends when no new name is found within the
+    /// retry budget, which is the filter's if there is one, or 1000 otherwise.
+    pub fn unique(mut self) -> Self {
+        self.seen = Some(HashSet::new());
+        self
+    }
+
+    /// Reject names where a part generated one of its learned samples verbatim. Word list parts
+    /// are exempt, since their samples are all they can generate. This uses the same retry
+    /// budget as [`GeneratorIter::unique`].
+    pub fn exclude_samples(mut self) -> Self {
+        self.exclude_samples = true;
+        self
+    }
+
+    /// Get the total number of names rejected so far. If this is high compared to the number of
+    /// names generated, the filter is probably too strict for the generator.
+    pub fn rejections(&self) -> usize {
+        self.rejections
+    }
+
+    /// Check whether the iterator ended because no acceptable name was found within the retry
+    /// budget.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Get the error that ended the iterator, if any.
+    pub fn error(&self) -> Option<&GenerateError> {
+        self.error.as_ref()
+    }
+
+    /// Generate the next name like `next`, but return the error if a part could not generate
+    /// anything, e.g. because it has not learned any samples. `Ok(None)` means that the iterator
+    /// is exhausted. After an error, the iterator ends.
+    pub fn try_next(&mut self) -> Result<Option<String>, GenerateError> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        if self.exhausted {
+            return Ok(None);
+        }
+
+        let max_retries = self.filter.as_ref().map(|f| f.max_retries()).unwrap_or(DEFAULT_MAX_RETRIES);
+        let mut retries = 0;
+        loop {
+            self.ws.result_total.clear();
+            let result = if self.is_part {
+                self.name.run_generate_part(&mut self.ws, &mut self.rng, self.index, self.exclude_samples)
+            } else {
+                self.name.run_generate(&mut self.ws, &mut self.rng, self.index, self.exclude_samples, 0)
+            };
+            let accepted = match result {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    self.error = Some(err.clone());
+                    return Err(err);
+                }
+            };
+
+            if accepted
+                && self.filter.as_ref().map(|f| f.accepts(&self.ws.result_total)).unwrap_or(true)
+                && self.seen.as_ref().map(|s| !s.contains(&self.ws.result_total)).unwrap_or(true)
+            {
+                break;
+            }
+
+            self.rejections += 1;
+            if retries == max_retries {
+                self.exhausted = true;
+                return Ok(None);
+            }
+            retries += 1;
+        }
+
+        if let Some(seen) = &mut self.seen {
+            seen.insert(self.ws.result_total.clone());
+        }
+
+        Ok(Some(self.ws.result_total.clone()))
+    }
 }
 
 impl<'a, T> Iterator for GeneratorIter<'a, T> where T: Rng {
     type Item = String;
 
+    /// Generate the next name. This returns `None` if the iterator is exhausted, or if a part
+    /// could not generate anything, in which case `error` tells why.
     fn next(&mut self) -> Option<Self::Item> {
-        self.ws.result_total.clear();
-        if self.is_part {
-            self.name.run_generate_part(&mut self.ws, &mut self.rng, self.index);
-        } else {
-            self.name.run_generate(&mut self.ws, &mut self.rng, self.index);
-        }
+        self.try_next().ok().flatten()
+    }
+}
 
-        Some(self.ws.result_total.clone())
+/// Parse the `30%:` at the start of an optional segment, if any, and return the probability. It's
+/// even odds without one.
+fn parse_probability(format: &str, pos: &mut usize) -> Result<f64, SyntaxError> {
+    let rest = &format[*pos..];
+    let digits = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+    if digits == 0 || !rest[digits..].starts_with("%:") {
+        return Ok(0.5);
+    }
+
+    match rest[..digits].parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => {
+            *pos += digits + 2;
+            Ok(percent / 100.0)
+        }
+        _ => Err(SyntaxError::new(*pos, "Expected a percentage from 0 to 100".to_owned())),
     }
 }
 
@@ -332,19 +884,15 @@ fn next_bracket(s: &str) -> (&str, usize, usize) {
     let mut start_found = false;
     let mut end = 0usize;
 
-    for (i, ch) in s.chars().enumerate() {
+    for (i, ch) in s.char_indices() {
         match ch {
-            '{' => {
-                if !start_found {
-                    start = i;
-                    start_found = true;
-                }
+            '{' if !start_found => {
+                start = i;
+                start_found = true;
             }
-            '}' => {
-                if start_found {
-                    end = i;
-                    break;
-                }
+            '}' if start_found => {
+                end = i;
+                break;
             }
             _ => {}
         }
@@ -356,3 +904,234 @@ fn next_bracket(s: &str) -> (&str, usize, usize) {
         (&s[0..0], s.len(), s.len())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sample, FormattingRule};
+    use crate::sample::sample_set;
+
+    #[test]
+    fn test_unique() {
+        let mut name = Name::new();
+        let mut part = NamePart::new_wordlist("first", &[]);
+        part.learn(&sample_set(&["aeris", "baldur", "celia", "dorian"])).unwrap();
+        name.add_part(part);
+
+        let mut iter = name.generate_part_seeded(7, "first").unwrap().unique();
+        let mut results: Vec<String> = (&mut iter).collect();
+        results.sort();
+
+        assert_eq!(results, vec!["aeris", "baldur", "celia", "dorian"]);
+        assert!(iter.is_exhausted());
+    }
+
+    #[test]
+    fn test_exclude_samples() {
+        let mut name = Name::new();
+        let mut first = NamePart::new_cfgrammar("first", &[], &[] as &[&str], false, false);
+        let mut tokens = SampleSet::with_labels(&["*", "*"]);
+        tokens.add_sample(Sample::Tokens(vec!["ma".to_owned(), "na".to_owned()]));
+        tokens.add_sample(Sample::Tokens(vec!["re".to_owned(), "la".to_owned()]));
+        first.learn(&tokens).unwrap();
+        let mut title = NamePart::new_wordlist("title", &[]);
+        title.learn(&sample_set(&["the bold"])).unwrap();
+        name.add_part(first);
+        name.add_part(title);
+        name.add_format("full", "{first}, {title}").unwrap();
+
+        let mut iter = name.generate_seeded(7, "full").unwrap().unique().exclude_samples();
+        let mut results: Vec<String> = (&mut iter).collect();
+        results.sort();
+
+        assert_eq!(results, vec!["mala, the bold", "rena, the bold"]);
+        assert!(iter.is_exhausted());
+    }
+
+    #[test]
+    fn test_unlearn() {
+        let mut name = Name::new();
+        name.add_part(NamePart::new_markov("first", &[], &[] as &[&str], false, false, false, false));
+        name.learn("first", &sample_set(&["aeris", "aerin", "baldur"])).unwrap();
+        name.learn("first", &sample_set(&["aerin"])).unwrap();
+
+        name.unlearn("first", &sample_set(&["aerin", "baldur"])).unwrap();
+        let part = name.parts().next().unwrap();
+        assert!(part.is_sample("aerin"));
+        assert!(!part.is_sample("baldur"));
+        assert_eq!(part.score("baldur"), None);
+
+        assert!(name.unlearn("first", &sample_set(&["baldur"])).is_err());
+        assert!(name.unlearn("last", &sample_set(&["aeris"])).is_err());
+        name.validate().unwrap();
+    }
+
+    #[test]
+    fn test_try_next() {
+        let mut name = Name::new();
+        let mut first = NamePart::new_wordlist("first", &[]);
+        first.learn(&sample_set(&["aeris", "baldur"])).unwrap();
+        name.add_part(first);
+        name.add_part(NamePart::new_markov("last", &[], &[] as &[&str], false, false, false, false));
+        name.add_format("first", "{first}").unwrap();
+        name.add_format("full", "{first} {last}").unwrap();
+        name.add_format("titled", "Sir {:full}").unwrap();
+
+        let mut iter = name.generate_seeded(7, "first").unwrap();
+        assert!(iter.try_next().unwrap().is_some());
+        assert!(iter.error().is_none());
+
+        let mut iter = name.generate_seeded(7, "titled").unwrap();
+        let err = iter.try_next().unwrap_err();
+        assert_eq!(err.part(), Some("last"));
+        assert_eq!(err.format(), Some("full"));
+        assert_eq!(err.to_string(), "parts::Markov in part last in format full: Nothing has been learned.");
+        assert_eq!(iter.next(), None);
+        assert!(iter.try_next().is_err());
+
+        let mut iter = name.generate_part_seeded(7, "last").unwrap();
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.error().unwrap().part(), Some("last"));
+        assert_eq!(iter.error().unwrap().format(), None);
+    }
+
+    #[test]
+    fn test_add_format() {
+        let mut name = Name::new();
+        let mut first = NamePart::new_wordlist("first", &[]);
+        first.learn(&sample_set(&["aeris"])).unwrap();
+        name.add_part(first);
+        name.add_format("first", "{first}").unwrap();
+
+        let offset = |name: &mut Name, format: &str| name.add_format("x", format).unwrap_err().offset();
+        assert_eq!(offset(&mut name, "Sir {frist}"), 5);
+        assert_eq!(offset(&mut name, "{first|=The |:}"), 13);
+        assert_eq!(offset(&mut name, "{first} }"), 8);
+        assert_eq!(offset(&mut name, "Þór {first"), 6);
+        assert_eq!(offset(&mut name, "{first{first}}"), 6);
+        assert_eq!(offset(&mut name, "{first|}"), 7);
+        assert_eq!(offset(&mut name, "{}"), 1);
+        assert_eq!(name.add_format("x", "{frist}").unwrap_err().highlight("{frist}"), "{frist}\n ^");
+        assert_eq!(name.formats().count(), 1);
+
+        name.add_format("braces", "{{{first}}} {{x}}").unwrap();
+        assert_eq!(name.generate_seeded(1, "braces").unwrap().next().unwrap(), "{aeris} {x}");
+
+        name.add_format_lenient("lenient", "Þór {frist} {first|:full} {:full}}");
+        assert_eq!(name.generate_seeded(1, "lenient").unwrap().next().unwrap(), "Þór {frist} aeris {:full}}");
+    }
+
+    #[test]
+    fn test_format_references() {
+        let mut name = Name::new();
+        let mut first = NamePart::new_wordlist("first", &[]);
+        first.learn(&sample_set(&["aeris"])).unwrap();
+        name.add_part(first);
+
+        name.add_format("titled", "Sir {:full}").unwrap();
+        assert!(name.validate().is_err());
+        assert!(name.generate_seeded(1, "titled").unwrap().try_next().is_err());
+        name.add_format("full", "{first} {:last}").unwrap();
+        name.add_format("last", "{=Stone}").unwrap();
+        name.validate().unwrap();
+        assert_eq!(name.generate_seeded(1, "titled").unwrap().next().unwrap(), "Sir aeris Stone");
+        assert_close(name.score_format("titled", "Sir aeris Stone"), 1.0);
+
+        let mut name = name.clone();
+        name.add_format("a", "{first}{? {:b}}").unwrap();
+        name.add_format("b", "{=of }{:a}").unwrap();
+        let err = name.validate().unwrap_err();
+        assert_eq!(err.to_string(), "ngen::NameFormat(a): Name format refers back to itself.");
+
+        name.add_format("loop", "{:loop}").unwrap();
+        let err = name.generate_seeded(1, "loop").unwrap().try_next().unwrap_err();
+        assert_eq!(err.format(), Some("loop"));
+        assert_eq!(name.score_format("loop", "aeris"), None);
+
+        let mut name = Name::new();
+        let mut first = NamePart::new_wordlist("first", &[]);
+        first.learn(&sample_set(&["aeris"])).unwrap();
+        name.add_part(first);
+        name.add_format("titled", "Sir {:full}").unwrap();
+        name.add_format_lenient("full", "{first} {unknown}");
+        name.validate().unwrap();
+        assert_eq!(name.generate_seeded(1, "titled").unwrap().next().unwrap(), "Sir aeris {unknown}");
+    }
+
+    #[test]
+    fn test_weighted_and_optional() {
+        let mut name = Name::new();
+        for (part_name, word) in [("first", "aeris"), ("middle", "bel"), ("last", "stone")].iter() {
+            let mut part = NamePart::new_wordlist(part_name, &[]);
+            part.learn(&sample_set(&[word])).unwrap();
+            name.add_part(part);
+        }
+        name.add_format("nick", "{=ace}").unwrap();
+        name.add_format("full", "{first*3|:nick}{?30%: {middle}} {last}").unwrap();
+        name.add_format("nested", "{first}{? {middle}{? {last}}}").unwrap();
+        name.add_format("brackets", "{first} [{last}]{? ({middle})}").unwrap();
+        name.validate().unwrap();
+
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for result in name.generate_seeded(3, "full").unwrap().take(4000) {
+            *counts.entry(result).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 4);
+        let share = |s: &str| counts[s] as f64 / 4000.0;
+        assert!((share("aeris stone") - 0.75 * 0.7).abs() < 0.03, "{:?}", counts);
+        assert!((share("ace bel stone") - 0.25 * 0.3).abs() < 0.03, "{:?}", counts);
+
+        assert_close(name.score_format("full", "aeris bel stone"), 0.75 * 0.3);
+        assert_close(name.score_format("full", "ace stone"), 0.25 * 0.7);
+        assert_eq!(name.score_format("full", "aeris bel"), None);
+        assert_close(name.score_format("nested", "aeris"), 0.5);
+        assert_close(name.score_format("nested", "aeris bel stone"), 0.25);
+        assert_eq!(name.score_format("nested", "aeris stone"), None);
+        let brackets: std::collections::BTreeSet<String> = name.generate_seeded(1, "brackets").unwrap().take(100).collect();
+        assert_eq!(brackets, ["aeris [stone]", "aeris [stone] (bel)"].iter().map(|s| (*s).to_owned()).collect());
+
+        let offset = |name: &mut Name, format: &str| name.add_format("x", format).unwrap_err().offset();
+        assert_eq!(offset(&mut name, "{first*0|:nick}"), 7);
+        assert_eq!(offset(&mut name, "{first*|:nick}"), 1);
+        assert_eq!(offset(&mut name, "{first*4294967295|:nick}"), 18);
+        assert_eq!(offset(&mut name, "{first*4294967290|:nick*6}"), 24);
+        assert_eq!(offset(&mut name, "{first}{? {middle}"), 7);
+        assert_eq!(offset(&mut name, "{first}} {middle}"), 7);
+        assert_eq!(offset(&mut name, "{first}{?120%: {middle}}"), 9);
+
+        name.formats.push(NameFormat{
+            name: "overflow".to_owned(),
+            parts: vec![FormatPart::Weighted(vec![(FormatPart::Part(0), u32::MAX), (FormatPart::Part(1), 1)])],
+        });
+        assert!(name.validate().is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let mut name = Name::new();
+        let mut first = NamePart::new_wordlist("first", &[FormattingRule::CapitalizeFirst]);
+        first.learn(&sample_set(&["aeris", "baldur", "bal"])).unwrap();
+        let mut last = NamePart::new_wordlist("last", &[FormattingRule::CapitalizeFirst]);
+        last.learn(&sample_set(&["dur", "stone"])).unwrap();
+        name.add_part(first);
+        name.add_part(last);
+        name.add_format("first", "{first}").unwrap();
+        name.add_format("full", "{first}{last}").unwrap();
+        name.add_format("titled", "{=Sir |=Lady }{:full}").unwrap();
+
+        assert_close(name.score_format("first", "Baldur"), 1.0 / 3.0);
+        assert_close(name.score_format("full", "AerisStone"), 1.0 / 6.0);
+        assert_close(name.score_format("full", "BalDur"), 1.0 / 6.0);
+        assert_close(name.score_format("titled", "Sir AerisStone"), 1.0 / 12.0);
+        assert_close(name.score("Baldur"), 1.0 / 3.0);
+        assert_eq!(name.score_format("full", "Baldurs"), None);
+        assert_eq!(name.score_format("titled", "AerisStone"), None);
+        assert_eq!(name.score("Celia"), None);
+        assert_eq!(name.score_format("stuff", "Baldur"), None);
+    }
+
+    fn assert_close(score: Option<f64>, expected: f64) {
+        let score = score.unwrap();
+        assert!((score - expected).abs() < 1e-9, "{} != {}", score, expected);
+    }
+}
@@ -0,0 +1,74 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// A normalization step for sample input. Without one, the same name written with precomposed
+/// and decomposed characters (e.g. `Å` and `A` + `◌̊`) is learned as two different names.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum NormalizationRule {
+    /// Unicode normalization form C, where letters and their diacritics are composed to one
+    /// character wherever possible.
+    Nfc,
+    /// Unicode normalization form D, where letters and their diacritics are separate characters.
+    Nfd,
+    /// Lowercase everything.
+    Lowercase,
+    /// Remove the diacritics from letters that decompose, e.g. `é` becomes `e`. Letters like `ø`
+    /// and `þ` are letters in their own right and are kept.
+    StripDiacritics,
+}
+
+impl NormalizationRule {
+    /// Parse a rule from its short form, which is `nfc`, `nfd`, `lowercase` or
+    /// `strip-diacritics`. This is the syntax used by definition files and the command-line tool.
+    pub fn parse(s: &str) -> Option<NormalizationRule> {
+        match s {
+            "nfc" => Some(NormalizationRule::Nfc),
+            "nfd" => Some(NormalizationRule::Nfd),
+            "lowercase" => Some(NormalizationRule::Lowercase),
+            "strip-diacritics" => Some(NormalizationRule::StripDiacritics),
+            _ => None,
+        }
+    }
+}
+
+/// Normalize a string with the rules, in order.
+pub fn normalize_string(s: &str, rules: &[NormalizationRule]) -> String {
+    let mut res = s.to_owned();
+    for rule in rules.iter() {
+        res = match rule {
+            NormalizationRule::Nfc => res.nfc().collect(),
+            NormalizationRule::Nfd => res.nfd().collect(),
+            NormalizationRule::Lowercase => res.to_lowercase(),
+            NormalizationRule::StripDiacritics => res.nfd().filter(|c| !is_combining_mark(*c)).collect(),
+        };
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_string() {
+        let composed = "\u{c5}sa";
+        let decomposed = "A\u{30a}sa";
+
+        assert_ne!(composed, decomposed);
+        assert_eq!(normalize_string(decomposed, &[NormalizationRule::Nfc]), composed);
+        assert_eq!(normalize_string(composed, &[NormalizationRule::Nfd]), decomposed);
+        assert_eq!(normalize_string(decomposed, &[NormalizationRule::Nfc, NormalizationRule::Lowercase]), "\u{e5}sa");
+        assert_eq!(normalize_string("Bjørn Ásgeirsson", &[NormalizationRule::StripDiacritics]), "Bjørn Asgeirsson");
+        assert_eq!(normalize_string("Tarō", &[]), "Tarō");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(NormalizationRule::parse("nfc"), Some(NormalizationRule::Nfc));
+        assert_eq!(NormalizationRule::parse("strip-diacritics"), Some(NormalizationRule::StripDiacritics));
+        assert_eq!(NormalizationRule::parse("NFC"), None);
+    }
+}
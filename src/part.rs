@@ -1,8 +1,11 @@
 use rand::Rng;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 
-use crate::{Markov, CFGrammar, FormattingRule, WorkingSet, SampleSet, LearnError, WordList};
-use crate::formatting::format_ws;
-use crate::core::ValidationError;
+use crate::{Sample, Markov, CFGrammar, Syllable, Pattern, FormattingRule, NormalizationRule, Phonotactics, WorkingSet, SampleSet, LearnError, WordList};
+use crate::formatting::{format_ws, format_string};
+use crate::normalize::normalize_string;
+use crate::core::{ValidationError, GenerateError, DEFAULT_MAX_ATTEMPTS};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone)]
@@ -13,14 +16,20 @@ enum PartGenerator {
     CFGrammar(CFGrammar),
     #[cfg_attr(feature = "serde", serde(rename="wordlist"))]
     WordList(WordList),
+    #[cfg_attr(feature = "serde", serde(rename="syllable"))]
+    Syllable(Syllable),
+    #[cfg_attr(feature = "serde", serde(rename="pattern"))]
+    Pattern(Pattern),
 }
 
 impl PartGenerator {
-    fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
+    fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
         match self {
-            PartGenerator::Markov(m) => m.generate(ws, rng),
-            PartGenerator::CFGrammar(c) => c.generate(ws, rng),
-            PartGenerator::WordList(wl) => wl.generate(ws, rng),
+            PartGenerator::Markov(m) => m.try_generate_with(ws, rng, max_attempts, phonotactics),
+            PartGenerator::CFGrammar(c) => c.try_generate_with(ws, rng, max_attempts, phonotactics),
+            PartGenerator::WordList(wl) => wl.try_generate_with(ws, rng, max_attempts, phonotactics),
+            PartGenerator::Syllable(sy) => sy.try_generate_with(ws, rng, max_attempts, phonotactics),
+            PartGenerator::Pattern(p) => p.try_generate_with(ws, rng, max_attempts, phonotactics),
         }
     }
 
@@ -29,6 +38,18 @@ impl PartGenerator {
             PartGenerator::Markov(m) => m.learn(sample_set),
             PartGenerator::CFGrammar(c) => c.learn(sample_set),
             PartGenerator::WordList(wl) => wl.learn(sample_set),
+            PartGenerator::Syllable(sy) => sy.learn(sample_set),
+            PartGenerator::Pattern(p) => p.learn(sample_set),
+        }
+    }
+
+    fn score(&self, s: &str) -> Option<f64> {
+        match self {
+            PartGenerator::Markov(m) => m.score(s),
+            PartGenerator::CFGrammar(c) => c.score(s),
+            PartGenerator::WordList(wl) => wl.score(s),
+            PartGenerator::Syllable(sy) => sy.score(s),
+            PartGenerator::Pattern(p) => p.score(s),
         }
     }
 
@@ -37,6 +58,8 @@ impl PartGenerator {
             PartGenerator::Markov(m) => m.validate(),
             PartGenerator::CFGrammar(c) => c.validate(),
             PartGenerator::WordList(wl) => wl.validate(),
+            PartGenerator::Syllable(sy) => sy.validate(),
+            PartGenerator::Pattern(p) => p.validate(),
         }
     }
 }
@@ -48,6 +71,15 @@ pub struct NamePart {
     name: String,
     generator: PartGenerator,
     format_rules: Vec<FormattingRule>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    normalization: Vec<NormalizationRule>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Phonotactics::is_empty"))]
+    phonotactics: Phonotactics,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="BTreeMap::is_empty"))]
+    samples: BTreeMap<String, usize>,
 }
 
 impl NamePart {
@@ -56,32 +88,215 @@ impl NamePart {
     }
 
     pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
-        self.generator.generate(ws, rng);
+        if self.generator.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS, &self.phonotactics).is_err() {
+            ws.result_str.clear();
+        }
         format_ws(ws, &self.format_rules);
     }
 
+    /// Generate a name like `generate`, but return an error naming this part if the generator has
+    /// not learned anything, or if it gave up after `max_attempts` restarts.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize) -> Result<(), GenerateError> {
+        self.generator.try_generate(ws, rng, max_attempts, &self.phonotactics).map_err(|e| e.with_part(&self.name))?;
+        format_ws(ws, &self.format_rules);
+
+        Ok(())
+    }
+
+    /// Generate, but skip the formatting and return false if `exclude_samples` is set and the
+    /// result is one of the learned samples.
+    pub(crate) fn generate_checked(&self, ws: &mut WorkingSet, rng: &mut impl Rng, exclude_samples: bool) -> Result<bool, GenerateError> {
+        self.generator.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS, &self.phonotactics).map_err(|e| e.with_part(&self.name))?;
+        if exclude_samples && self.is_sample(&ws.result_str) {
+            return Ok(false);
+        }
+
+        format_ws(ws, &self.format_rules);
+        Ok(true)
+    }
+
+    /// Check if the unformatted result is one of the learned samples. Word list parts do not keep
+    /// track of this, since all they can produce is their samples.
+    pub fn is_sample(&self, result: &str) -> bool {
+        self.samples.contains_key(result)
+    }
+
+    /// Get the likelihood of the part generating this name, or `None` if it cannot generate it.
+    /// Since the formatting rules can't be reversed exactly, the name is scored both as-is and
+    /// with capitalization and character replacements undone, and the highest score is used.
+    ///
+    /// The part's normalization is applied to the name first, like it is to learned samples. The
+    /// phonotactic rules apply to the generated name before formatting, so each candidate is only
+    /// scored if it passes them.
+    pub fn score(&self, name: &str) -> Option<f64> {
+        let name = &normalize_string(name, &self.normalization);
+        let mut unformatted: String = name.to_owned();
+        for rule in self.format_rules.iter().rev() {
+            match *rule {
+                FormattingRule::ReplaceChar{from, to} => {
+                    unformatted = unformatted.chars().map(|c| if c == to { from } else { c }).collect();
+                }
+                FormattingRule::CapitalizeFirst | FormattingRule::CapitalizeDefault | FormattingRule::CapitalizeAfter(_) => {
+                    unformatted = unformatted.to_lowercase();
+                }
+                FormattingRule::RemoveChar(_) => {}
+            }
+        }
+
+        let unformatted = normalize_string(&unformatted, &self.normalization);
+        let score_candidate = |candidate: &str| if self.phonotactics.check(candidate) {
+            self.generator.score(candidate)
+        } else {
+            None
+        };
+        match (score_candidate(name), score_candidate(&unformatted)) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Iterate over every distinct name the part can generate that passes the phonotactic rules,
+    /// with the formatting rules applied. This is only possible with `cfgrammar` and `wordlist` parts, and it returns `None` for
+    /// `markov`, `syllable` and `pattern` parts.
+    pub fn outputs(&self) -> Option<Box<dyn Iterator<Item=String> + '_>> {
+        match &self.generator {
+            PartGenerator::Markov(_) | PartGenerator::Syllable(_) | PartGenerator::Pattern(_) => None,
+            PartGenerator::CFGrammar(c) => Some(Box::new(
+                c.outputs()
+                    .filter(move |s| self.phonotactics.check(s))
+                    .map(move |s| format_string(&s, &self.format_rules))
+            )),
+            PartGenerator::WordList(wl) => Some(Box::new(
+                wl.outputs()
+                    .filter(move |s| self.phonotactics.check(s))
+                    .map(move |s| format_string(s, &self.format_rules))
+            )),
+        }
+    }
+
+    /// Set the creativity of a markov part, see [`Markov::set_creativity`]. This returns false
+    /// and does nothing for other parts.
+    pub fn set_creativity(&mut self, creativity: f64) -> bool {
+        match &mut self.generator {
+            PartGenerator::Markov(m) => {
+                m.set_creativity(creativity);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn normalization(&self) -> &[NormalizationRule] {
+        &self.normalization
+    }
+
+    /// Set the normalization rules for samples learned and unlearned by this part, and for names
+    /// passed to `score`. They're kept with the part, so a model that's saved and loaded keeps
+    /// treating input the same way. Set them before learning anything, and give any initial
+    /// tokens in the same form.
+    pub fn set_normalization(&mut self, rules: &[NormalizationRule]) {
+        self.normalization = rules.to_vec();
+    }
+
+    pub fn phonotactics(&self) -> &Phonotactics {
+        &self.phonotactics
+    }
+
+    /// Set the phonotactic rules that generated names must follow. See [`Phonotactics`].
+    pub fn set_phonotactics(&mut self, phonotactics: Phonotactics) {
+        self.phonotactics = phonotactics;
+    }
+
+    fn normalize_samples<'a>(&self, sample_set: &'a SampleSet) -> Cow<'a, SampleSet> {
+        if self.normalization.is_empty() {
+            return Cow::Borrowed(sample_set);
+        }
+
+        let mut normalized = sample_set.clone();
+        normalized.set_normalization(&self.normalization);
+        Cow::Owned(normalized)
+    }
+
     pub fn learn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
-        self.generator.learn(sample_set)
+        let sample_set = &*self.normalize_samples(sample_set);
+        self.generator.learn(sample_set)?;
+
+        if let PartGenerator::WordList(_) = self.generator {
+            return Ok(());
+        }
+        for sample in sample_set.samples() {
+            *self.samples.entry(sample_string(sample)).or_insert(0) += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Forget the samples, which must have been learned by this part before. See the `unlearn`
+    /// function of the generators for what can and cannot be undone.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let sample_set = &*self.normalize_samples(sample_set);
+        match &mut self.generator {
+            PartGenerator::Markov(m) => m.unlearn(sample_set)?,
+            PartGenerator::CFGrammar(c) => c.unlearn(sample_set)?,
+            PartGenerator::WordList(wl) => return wl.unlearn(sample_set),
+            PartGenerator::Syllable(sy) => sy.unlearn(sample_set)?,
+            PartGenerator::Pattern(p) => p.unlearn(sample_set)?,
+        }
+
+        for sample in sample_set.samples() {
+            let key = sample_string(sample);
+            if let Some(count) = self.samples.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    self.samples.remove(&key);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub fn validate(&self) -> Result<(), ValidationError> {
-        self.generator.validate().map_err(|err| err.with_name(&self.name))
+        self.generator.validate().map_err(|err| err.with_name(&self.name))?;
+        self.phonotactics.validate().map_err(|err| err.with_name(&self.name))
     }
 
     pub fn new_markov<S: AsRef<str>>(name: &str, format_rules: &[FormattingRule], initial_tokens: &[S], lrs: bool, lrm: bool, lre: bool, rlf: bool) -> NamePart {
         NamePart {
             name: name.to_owned(),
             format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
             generator: PartGenerator::Markov(
                 Markov::with_constraints(initial_tokens, lrs, lrm, lre, rlf),
             ),
         }
     }
 
+    /// Create a markov part with a different order than the default of 2. See
+    /// [`Markov::with_order`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_markov_with_order<S: AsRef<str>>(name: &str, format_rules: &[FormattingRule], initial_tokens: &[S], order: usize, lrs: bool, lrm: bool, lre: bool, rlf: bool) -> NamePart {
+        NamePart {
+            name: name.to_owned(),
+            format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
+            generator: PartGenerator::Markov(
+                Markov::with_order(initial_tokens, order, lrs, lrm, lre, rlf),
+            ),
+        }
+    }
+
     pub fn new_cfgrammar<S: AsRef<str>>(name: &str, format_rules: &[FormattingRule], initial_subtokens: &[S], rlf: bool, ral: bool) -> NamePart {
         NamePart {
             name: name.to_owned(),
             format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
             generator: PartGenerator::CFGrammar(
                 CFGrammar::new(initial_subtokens, rlf, ral),
             )
@@ -92,9 +307,159 @@ impl NamePart {
         NamePart {
             name: name.to_owned(),
             format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
             generator: PartGenerator::WordList(
                 WordList::new(),
             )
         }
     }
-}
\ No newline at end of file
+
+    /// Create a syllable part, which can be empty to learn from samples or written by hand. See
+    /// [`Syllable`].
+    pub fn new_syllable(name: &str, format_rules: &[FormattingRule], syllable: Syllable) -> NamePart {
+        NamePart {
+            name: name.to_owned(),
+            format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
+            generator: PartGenerator::Syllable(syllable),
+        }
+    }
+
+    /// Create a pattern part, which is written by hand and does not learn. See [`Pattern`].
+    pub fn new_pattern(name: &str, format_rules: &[FormattingRule], pattern: Pattern) -> NamePart {
+        NamePart {
+            name: name.to_owned(),
+            format_rules: format_rules.to_vec(),
+            normalization: Vec::new(),
+            phonotactics: Phonotactics::new(),
+            samples: BTreeMap::new(),
+            generator: PartGenerator::Pattern(pattern),
+        }
+    }
+}
+
+fn sample_string(sample: &Sample) -> String {
+    match sample {
+        Sample::Word(w) | Sample::WordWeighted(w, _) => w.clone(),
+        Sample::Tokens(tokens) => tokens.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sample;
+
+    #[test]
+    fn test_score() {
+        let mut part = NamePart::new_wordlist("last", &[
+            FormattingRule::CapitalizeFirst,
+            FormattingRule::CapitalizeAfter('_'),
+            FormattingRule::ReplaceChar{from: '_', to: ' '},
+        ]);
+        let mut sample_set = SampleSet::new();
+        sample_set.add_sample(Sample::Word("du_bois".to_owned()));
+        sample_set.add_sample(Sample::Word("Smith".to_owned()));
+        part.learn(&sample_set).unwrap();
+
+        assert_eq!(part.score("Du Bois"), Some(0.5));
+        assert_eq!(part.score("du_bois"), Some(0.5));
+        assert_eq!(part.score("Smith"), Some(0.5));
+        assert_eq!(part.score("Jones"), None);
+    }
+
+    #[test]
+    fn test_phonotactics() {
+        use crate::PhonotacticRule;
+        use rand::{SeedableRng, rngs::SmallRng};
+
+        let words = ["astrid", "ingrid", "sigrun", "halvard", "gunnar", "ragnhild", "bjorn", "solveig", "torstein", "eirik"];
+        let mut sample_set = SampleSet::new();
+        for word in words.iter() {
+            sample_set.add_sample(Sample::Word((*word).to_owned()));
+        }
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_rule(PhonotacticRule::parse("max-run:consonant:2").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("not-end:liquid").unwrap());
+        phonotactics.add_class("liquid", "lr");
+
+        let mut parts = [
+            NamePart::new_markov("first", &[], &[] as &[&str], false, false, false, false),
+            NamePart::new_wordlist("first", &[]),
+        ];
+        for part in parts.iter_mut() {
+            part.learn(&sample_set).unwrap();
+            part.set_phonotactics(phonotactics.clone());
+            part.validate().unwrap();
+
+            let mut ws = WorkingSet::new();
+            let mut rng = SmallRng::seed_from_u64(7);
+            for _ in 0..100 {
+                part.try_generate(&mut ws, &mut rng, 1024).unwrap();
+                assert!(phonotactics.check(&ws.result_str), "{}", ws.result_str);
+            }
+            assert_eq!(part.score("torstein"), None);
+        }
+
+        phonotactics.add_rule(PhonotacticRule::parse("not-start:vowel").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("not-start:consonant").unwrap());
+        parts[0].set_phonotactics(phonotactics);
+        assert!(parts[0].try_generate(&mut WorkingSet::new(), &mut SmallRng::seed_from_u64(7), 100).is_err());
+    }
+
+    #[test]
+    fn test_phonotactics_before_formatting() {
+        use crate::PhonotacticRule;
+
+        let mut part = NamePart::new_wordlist("last", &[
+            FormattingRule::CapitalizeFirst,
+            FormattingRule::CapitalizeAfter('_'),
+            FormattingRule::ReplaceChar{from: '_', to: ' '},
+        ]);
+        let mut sample_set = SampleSet::new();
+        for word in ["du_bois", "smith", "gunnar"].iter() {
+            sample_set.add_sample(Sample::Word((*word).to_owned()));
+        }
+        part.learn(&sample_set).unwrap();
+
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_class("space", " ");
+        phonotactics.add_class("liquid", "lr");
+        phonotactics.add_rule(PhonotacticRule::parse("max-run:space:0").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("not-end:liquid").unwrap());
+        part.set_phonotactics(phonotactics);
+        part.validate().unwrap();
+
+        let outputs: Vec<String> = part.outputs().unwrap().collect();
+        assert_eq!(outputs, vec!["Du Bois".to_owned(), "Smith".to_owned()]);
+        assert!(part.score("Du Bois").is_some());
+        assert!(part.score("Smith").is_some());
+        assert_eq!(part.score("Gunnar"), None);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let mut part = NamePart::new_wordlist("first", &[FormattingRule::CapitalizeFirst]);
+        part.set_normalization(&[NormalizationRule::Nfc, NormalizationRule::Lowercase]);
+
+        let mut sample_set = SampleSet::new();
+        sample_set.add_sample(Sample::Word("\u{c5}sa".to_owned()));
+        sample_set.add_sample(Sample::Word("A\u{30a}sa".to_owned()));
+        sample_set.add_sample(Sample::Word("s\u{f8}ren".to_owned()));
+        part.learn(&sample_set).unwrap();
+
+        let outputs: Vec<String> = part.outputs().unwrap().collect();
+        assert_eq!(outputs, vec!["\u{c5}sa", "S\u{f8}ren"]);
+        assert_eq!(part.score("A\u{30a}sa"), part.score("\u{e5}sa"));
+        assert!(part.score("\u{e5}sa").unwrap() > 0.5);
+
+        let mut sample_set = SampleSet::new();
+        sample_set.add_sample(Sample::Word("S\u{f8}ren".to_owned()));
+        part.unlearn(&sample_set).unwrap();
+        assert_eq!(part.score("s\u{f8}ren"), None);
+    }
+}
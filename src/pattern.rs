@@ -0,0 +1,463 @@
+use rand::Rng;
+use std::collections::BTreeMap;
+use crate::{WorkingSet, Phonotactics, SampleSet, LearnError};
+use crate::core::{ValidationError, GenerateError, SyntaxError, DEFAULT_MAX_ATTEMPTS};
+
+const SYLLABLES: &[&str] = &[
+    "ach", "ack", "ad", "age", "ald", "ale", "an", "ang", "ar", "ard", "as", "ash", "at", "ath", "augh", "aw", "ban",
+    "bel", "bur", "cer", "cha", "che", "dan", "dar", "del", "den", "dra", "dyn", "ech", "eld", "elm", "em", "en", "end",
+    "eng", "enth", "er", "ess", "est", "et", "gar", "gha", "hat", "hin", "hon", "ia", "ight", "ild", "im", "ina", "ine",
+    "ing", "ir", "is", "iss", "it", "kal", "kel", "kim", "kin", "ler", "lor", "lye", "mor", "mos", "nal", "ny", "nys",
+    "old", "om", "on", "or", "orm", "os", "ough", "per", "pol", "qua", "que", "rad", "rak", "ran", "ray", "ril", "ris",
+    "rod", "roth", "ryn", "sam", "say", "ser", "shy", "skel", "sul", "tai", "tan", "tas", "ther", "tia", "tin", "ton",
+    "tor", "tur", "um", "und", "unt", "urn", "usk", "ust", "ver", "ves", "vor", "war", "wor", "yer",
+];
+const VOWELS: &[&str] = &["a", "e", "i", "o", "u", "y"];
+const VOWEL_COMBOS: &[&str] = &[
+    "a", "e", "i", "o", "u", "y", "ae", "ai", "au", "ay", "ea", "ee", "ei", "eu", "ey", "ia", "ie", "oe", "oi", "oo",
+    "ou", "ui",
+];
+const CONSONANTS: &[&str] = &[
+    "b", "c", "d", "f", "g", "h", "j", "k", "l", "m", "n", "p", "q", "r", "s", "t", "v", "w", "x", "y", "z",
+];
+const BEGINNINGS: &[&str] = &[
+    "b", "bl", "br", "c", "ch", "chr", "cl", "cr", "d", "dr", "f", "g", "h", "j", "k", "l", "ll", "m", "n", "p", "ph",
+    "qu", "r", "rh", "s", "sch", "sh", "sl", "sm", "sn", "st", "str", "sw", "t", "th", "thr", "tr", "v", "w", "wh", "y",
+    "z", "zh",
+];
+const CONSONANT_COMBOS: &[&str] = &[
+    "b", "c", "ch", "ck", "d", "f", "g", "gh", "h", "k", "l", "ld", "ll", "lt", "m", "n", "nd", "nn", "nt", "p", "ph",
+    "q", "r", "rd", "rr", "rt", "s", "sh", "ss", "st", "t", "th", "v", "w", "y", "z",
+];
+
+fn builtin_class(symbol: char) -> Option<&'static [&'static str]> {
+    match symbol {
+        's' => Some(SYLLABLES),
+        'v' => Some(VOWELS),
+        'V' => Some(VOWEL_COMBOS),
+        'c' => Some(CONSONANTS),
+        'B' => Some(BEGINNINGS),
+        'C' => Some(CONSONANT_COMBOS),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+enum Node {
+    Literal(String),
+    Class(char),
+    Group{alternatives: Vec<(Vec<Node>, u32)>, total: u32},
+    Optional(Box<Node>),
+}
+
+/// The Pattern generator builds names from a template like `<B><V>'<C>`, in the style of the
+/// classic fantasy name generators. It doesn't learn from samples, but it can be mixed with parts
+/// that do in a [`Name`](crate::Name).
+///
+/// * Letters are character classes, which pick one of their items at random. `s` is a syllable,
+///   `v` a vowel, `V` a vowel or vowel combination, `c` a consonant, `B` a consonant or
+///   combination that fits at the beginning of a word, and `C` any consonant or combination.
+///   Custom classes can be added with [`Pattern::parse_with_classes`], and they replace the
+///   built-in ones with the same letter.
+/// * Everything else is literal text, and `\` makes the next character literal too.
+/// * `<...>` is a group and `(...)` is a group where letters are literal text. Groups can be
+///   nested, and `|` separates alternatives, e.g. `<B|(th)>`. An alternative can be given a weight
+///   with `*`, e.g. `<s*3|v>`. Alternatives can be empty.
+/// * `?` makes the character, class or group before it optional, with even odds.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Pattern {
+    source: String,
+    nodes: Vec<Node>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    classes: Vec<(char, Vec<String>)>,
+}
+
+impl Pattern {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Generate a name. You need to provide your own WorkingSet and Rng, which is necessary to save
+    /// on allocations. A dependent application should use the full name generator interface instead
+    pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
+        if self.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS).is_err() {
+            ws.result_str.clear();
+        }
+    }
+
+    /// Generate a name like `generate`, but return an error if no name is found after
+    /// `max_attempts` tries. Without phonotactic rules, the first try always succeeds.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize) -> Result<(), GenerateError> {
+        self.try_generate_with(ws, rng, max_attempts, &Phonotactics::new())
+    }
+
+    /// Generate a name like `try_generate` that follows the phonotactic rules. The whole name is
+    /// generated before it's checked, since a pattern can't backtrack.
+    pub fn try_generate_with(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
+        for _ in 0..max_attempts {
+            ws.result_str.clear();
+            self.generate_nodes(&self.nodes, &mut ws.result_str, rng);
+            if phonotactics.check(&ws.result_str) {
+                return Ok(());
+            }
+        }
+
+        Err(GenerateError::new("parts::Pattern", "No valid output found within the attempt budget."))
+    }
+
+    fn generate_nodes(&self, nodes: &[Node], target: &mut String, rng: &mut impl Rng) {
+        for node in nodes.iter() {
+            self.generate_node(node, target, rng);
+        }
+    }
+
+    fn generate_node(&self, node: &Node, target: &mut String, rng: &mut impl Rng) {
+        match node {
+            Node::Literal(s) => target.push_str(s),
+            Node::Class(symbol) => {
+                if let Some((_, items)) = self.classes.iter().find(|(c, _)| c == symbol) {
+                    target.push_str(&items[rng.gen_range(0, items.len())]);
+                } else if let Some(items) = builtin_class(*symbol) {
+                    target.push_str(items[rng.gen_range(0, items.len())]);
+                }
+            }
+            Node::Group{alternatives, total} => {
+                let mut roll = rng.gen_range(0, *total);
+                for (nodes, weight) in alternatives.iter() {
+                    if roll < *weight {
+                        self.generate_nodes(nodes, target, rng);
+                        break;
+                    }
+                    roll -= *weight;
+                }
+            }
+            Node::Optional(node) => {
+                if rng.gen::<bool>() {
+                    self.generate_node(node, target, rng);
+                }
+            }
+        }
+    }
+
+    /// Get the probability of generating the string, or `None` if it can't be generated at all.
+    pub fn score(&self, s: &str) -> Option<f64> {
+        let mut starts = BTreeMap::new();
+        starts.insert(0, 1.0);
+
+        match self.score_nodes(&self.nodes, s, starts).get(&s.len()) {
+            Some(score) if *score > 0.0 => Some(*score),
+            _ => None,
+        }
+    }
+
+    /// Get the probability of ending up at each position after the nodes, given the probability
+    /// of starting at each position.
+    fn score_nodes(&self, nodes: &[Node], s: &str, starts: BTreeMap<usize, f64>) -> BTreeMap<usize, f64> {
+        let mut current = starts;
+        for node in nodes.iter() {
+            let mut next = BTreeMap::new();
+            for (pos, p) in current.iter() {
+                for (end, q) in self.score_node(node, s, *pos) {
+                    *next.entry(end).or_insert(0.0) += p * q;
+                }
+            }
+
+            current = next;
+        }
+
+        current
+    }
+
+    fn score_node(&self, node: &Node, s: &str, pos: usize) -> BTreeMap<usize, f64> {
+        let mut res = BTreeMap::new();
+        let remainder = &s[pos..];
+
+        match node {
+            Node::Literal(l) => {
+                if remainder.starts_with(l.as_str()) {
+                    res.insert(pos + l.len(), 1.0);
+                }
+            }
+            Node::Class(symbol) => {
+                let items: Vec<&str> = match self.classes.iter().find(|(c, _)| c == symbol) {
+                    Some((_, items)) => items.iter().map(|s| s.as_str()).collect(),
+                    None => builtin_class(*symbol).map(|items| items.to_vec()).unwrap_or_default(),
+                };
+                for item in items.iter().filter(|item| remainder.starts_with(*item)) {
+                    *res.entry(pos + item.len()).or_insert(0.0) += 1.0 / items.len() as f64;
+                }
+            }
+            Node::Group{alternatives, total} => {
+                for (nodes, weight) in alternatives.iter() {
+                    let mut starts = BTreeMap::new();
+                    starts.insert(pos, *weight as f64 / *total as f64);
+                    for (end, p) in self.score_nodes(nodes, s, starts) {
+                        *res.entry(end).or_insert(0.0) += p;
+                    }
+                }
+            }
+            Node::Optional(node) => {
+                res.insert(pos, 0.5);
+                for (end, p) in self.score_node(node, s, pos) {
+                    *res.entry(end).or_insert(0.0) += 0.5 * p;
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Patterns are written by hand, so this returns an error unless the sample set is empty.
+    pub fn learn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        match sample_set.samples().first() {
+            Some(sample) => Err(LearnError::new(6, "Pattern parts do not learn from samples".to_owned(), Some(sample.clone()))),
+            None => Ok(()),
+        }
+    }
+
+    /// See `learn`.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        self.learn(sample_set)
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.classes.iter().any(|(_, items)| items.is_empty()) {
+            return Err(ValidationError::new("parts::Pattern", "class without items."));
+        }
+
+        self.validate_nodes(&self.nodes)
+    }
+
+    fn validate_nodes(&self, nodes: &[Node]) -> Result<(), ValidationError> {
+        for node in nodes.iter() {
+            match node {
+                Node::Literal(_) => {}
+                Node::Class(symbol) => {
+                    if builtin_class(*symbol).is_none() && !self.classes.iter().any(|(c, _)| c == symbol) {
+                        return Err(ValidationError::new("parts::Pattern", "unknown class."));
+                    }
+                }
+                Node::Group{alternatives, total} => {
+                    if *total == 0 || alternatives.iter().try_fold(0u32, |sum, (_, w)| sum.checked_add(*w)) != Some(*total) {
+                        return Err(ValidationError::new("parts::Pattern", "incorrect group weights."));
+                    }
+                    for (nodes, _) in alternatives.iter() {
+                        self.validate_nodes(nodes)?;
+                    }
+                }
+                Node::Optional(node) => self.validate_nodes(std::slice::from_ref(node))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a pattern with the built-in classes.
+    pub fn parse(source: &str) -> Result<Pattern, SyntaxError> {
+        Self::parse_with_classes::<&str>(source, &[])
+    }
+
+    /// Parse a pattern with custom classes, which are a letter and the strings it can become.
+    pub fn parse_with_classes<S: AsRef<str>>(source: &str, classes: &[(char, &[S])]) -> Result<Pattern, SyntaxError> {
+        let classes: Vec<(char, Vec<String>)> = classes.iter()
+            .map(|(c, items)| (*c, items.iter().map(|s| s.as_ref().to_owned()).collect()))
+            .collect();
+
+        let mut parser = Parser{
+            chars: source.chars().collect(),
+            pos: 0,
+            classes: &classes,
+        };
+        let nodes = parser.parse_sequence(false, None)?;
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(parser.pos, format!("Unexpected '{}'", c)));
+        }
+        if nodes.is_empty() {
+            return Err(SyntaxError::new(0, "Pattern is empty".to_owned()));
+        }
+
+        Ok(Pattern{
+            source: source.to_owned(),
+            nodes,
+            classes,
+        })
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    classes: &'a [(char, Vec<String>)],
+}
+
+impl<'a> Parser<'a> {
+    /// Make an error at a character position, which is reported as a byte offset.
+    fn error(&self, pos: usize, message: String) -> SyntaxError {
+        SyntaxError::new(self.chars[..pos].iter().map(|c| c.len_utf8()).sum(), message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    /// Parse until the end, or until a `|`, `*` or the closing character of the group.
+    fn parse_sequence(&mut self, literal: bool, close: Option<char>) -> Result<Vec<Node>, SyntaxError> {
+        let mut nodes: Vec<Node> = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if close.is_some() && (c == '|' || c == '*' || Some(c) == close) {
+                break;
+            }
+
+            let start = self.pos;
+            self.pos += 1;
+            match c {
+                '\\' => match self.peek() {
+                    Some(c) => {
+                        self.pos += 1;
+                        nodes.push(Node::Literal(c.to_string()));
+                    }
+                    None => return Err(self.error(start, "Nothing to escape".to_owned())),
+                },
+                '<' => nodes.push(self.parse_group(false, '>', start)?),
+                '(' => nodes.push(self.parse_group(true, ')', start)?),
+                '?' => match nodes.pop() {
+                    Some(Node::Optional(_)) | None => return Err(self.error(start, "Nothing to make optional".to_owned())),
+                    Some(node) => nodes.push(Node::Optional(Box::new(node))),
+                },
+                '>' | ')' | '|' | '*' => return Err(self.error(start, format!("Unexpected '{}'", c))),
+                c if !literal && c.is_ascii_alphabetic() => {
+                    match self.classes.iter().find(|(symbol, _)| *symbol == c) {
+                        Some((_, items)) if items.is_empty() => {
+                            return Err(self.error(start, format!("Class '{}' has no items", c)));
+                        }
+                        Some(_) => {}
+                        None if builtin_class(c).is_some() => {}
+                        None => return Err(self.error(start, format!("Unknown class '{}'", c))),
+                    }
+                    nodes.push(Node::Class(c));
+                }
+                c => nodes.push(Node::Literal(c.to_string())),
+            }
+        }
+
+        // Merge the literal characters that weren't made optional.
+        let mut merged: Vec<Node> = Vec::with_capacity(nodes.len());
+        for node in nodes.into_iter() {
+            match (merged.last_mut(), node) {
+                (Some(Node::Literal(prev)), Node::Literal(s)) => prev.push_str(&s),
+                (_, node) => merged.push(node),
+            }
+        }
+
+        Ok(merged)
+    }
+
+    fn parse_group(&mut self, literal: bool, close: char, start: usize) -> Result<Node, SyntaxError> {
+        let mut alternatives = Vec::new();
+        let mut total: u32 = 0;
+
+        loop {
+            let mut weight_start = self.pos;
+            let nodes = self.parse_sequence(literal, Some(close))?;
+
+            let mut weight = 1;
+            if self.peek() == Some('*') {
+                self.pos += 1;
+                let digits_start = self.pos;
+                weight_start = digits_start;
+                while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+                    self.pos += 1;
+                }
+
+                let digits: String = self.chars[digits_start..self.pos].iter().collect();
+                weight = match digits.parse::<u32>() {
+                    Ok(weight) if weight > 0 => weight,
+                    _ => return Err(self.error(digits_start, "Expected a weight above zero".to_owned())),
+                };
+            }
+            total = match total.checked_add(weight) {
+                Some(total) => total,
+                None => return Err(self.error(weight_start, format!("The weights in this group add up to more than {}", u32::MAX))),
+            };
+            alternatives.push((nodes, weight));
+
+            match self.peek() {
+                Some('|') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(c) => return Err(self.error(self.pos, format!("Expected '|' or '{}', found '{}'", close, c))),
+                None => return Err(self.error(start, format!("Unclosed '{}'", self.chars[start]))),
+            }
+        }
+
+        Ok(Node::Group{alternatives, total})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn test_generate() {
+        let pattern = Pattern::parse_with_classes("<B><V>'<x*3|(ar)>?", &[('x', &["ath", "ek"])]).unwrap();
+        pattern.validate().unwrap();
+
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(11);
+        let mut seen_short = false;
+        for _ in 0..200 {
+            pattern.generate(&mut ws, &mut rng);
+            assert!(ws.result_str.contains('\''), "{}", ws.result_str);
+            assert!(pattern.score(&ws.result_str).unwrap() > 0.0, "{}", ws.result_str);
+            seen_short |= ws.result_str.ends_with('\'');
+        }
+        assert!(seen_short);
+    }
+
+    #[test]
+    fn test_score() {
+        let pattern = Pattern::parse("(th|k)(a*3|o)?").unwrap();
+
+        assert!((pattern.score("th").unwrap() - 0.25).abs() < 1e-9);
+        assert!((pattern.score("ka").unwrap() - 0.1875).abs() < 1e-9);
+        assert!((pattern.score("ko").unwrap() - 0.0625).abs() < 1e-9);
+        assert_eq!(pattern.score("kk"), None);
+
+        let pattern = Pattern::parse("\\v<v>").unwrap();
+        assert!((pattern.score("va").unwrap() - 1.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let offset = |source: &str| Pattern::parse(source).err().unwrap().offset();
+
+        assert_eq!(offset("<B><V"), 3);
+        assert_eq!(offset("<B>(ar|x"), 3);
+        assert_eq!(offset("<s><X>"), 4);
+        assert_eq!(offset("s>"), 1);
+        assert_eq!(offset("<s|v)>"), 4);
+        assert_eq!(offset("<s*0|v>"), 3);
+        assert_eq!(offset("<s*|v>"), 3);
+        assert_eq!(offset("<s*4294967295|v>"), 14);
+        assert_eq!(offset("<s*4294967290|v*6>"), 16);
+        assert_eq!(offset("?s"), 0);
+        assert_eq!(offset("s??"), 2);
+        assert_eq!(offset("s\\"), 1);
+        assert_eq!(offset(""), 0);
+        assert_eq!(offset("äs<ö"), 3);
+
+        let err = Pattern::parse_with_classes::<&str>("<B>q", &[('q', &[])]).err().unwrap();
+        assert_eq!(err.offset(), 3);
+        assert_eq!(err.highlight("<B>q"), "<B>q\n   ^");
+    }
+}
@@ -0,0 +1,190 @@
+use crate::core::ValidationError;
+
+pub(crate) const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøùúûüýÿāēīōūœ";
+
+/// A rule on which sequences of letters a name may contain. The rules refer to character classes
+/// by name, where `vowel` and `consonant` are built in and others are added with
+/// [`Phonotactics::add_class`].
+#[derive(Eq, PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum PhonotacticRule {
+    /// No more than `max` letters of the class in a row.
+    MaxRun{class: String, max: usize},
+    /// The name must not start with a letter of the class.
+    NotStart(String),
+    /// The name must not end with a letter of the class.
+    NotEnd(String),
+    /// The name must contain at least one letter of the class.
+    Require(String),
+}
+
+impl PhonotacticRule {
+    /// Parse a rule from its short form, which is `max-run:<class>:<n>`, `not-start:<class>`,
+    /// `not-end:<class>` or `require:<class>`. This is the syntax used by definition files and the
+    /// command-line tool.
+    pub fn parse(s: &str) -> Option<PhonotacticRule> {
+        let args: Vec<&str> = s.split(':').collect();
+
+        match args.as_slice() {
+            ["max-run", class, max] => Some(PhonotacticRule::MaxRun{class: (*class).to_owned(), max: max.parse().ok()?}),
+            ["not-start", class] => Some(PhonotacticRule::NotStart((*class).to_owned())),
+            ["not-end", class] => Some(PhonotacticRule::NotEnd((*class).to_owned())),
+            ["require", class] => Some(PhonotacticRule::Require((*class).to_owned())),
+            _ => None,
+        }
+    }
+
+    fn class(&self) -> &str {
+        match self {
+            PhonotacticRule::MaxRun{class, ..} => class,
+            PhonotacticRule::NotStart(class) | PhonotacticRule::NotEnd(class) | PhonotacticRule::Require(class) => class,
+        }
+    }
+}
+
+/// A set of phonotactic rules for a [`NamePart`](crate::NamePart). They're checked while the
+/// name is generated, so the `markov` and `cfgrammar` generators can backtrack as soon as a
+/// partial name breaks a rule instead of throwing away whole names.
+///
+/// The rules apply to the generated name before the formatting rules, and letters are matched
+/// against the classes in lowercase.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Phonotactics {
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    classes: Vec<(String, String)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    rules: Vec<PhonotacticRule>,
+}
+
+impl Phonotactics {
+    /// Add or replace a character class. Replacing `vowel` changes what `consonant` means, since
+    /// a consonant is any other letter.
+    pub fn add_class(&mut self, name: &str, chars: &str) {
+        let chars: String = chars.chars().flat_map(|c| c.to_lowercase()).collect();
+
+        match self.classes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, c)) => *c = chars,
+            None => self.classes.push((name.to_owned(), chars)),
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: PhonotacticRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn rules(&self) -> &[PhonotacticRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check whether a partial name can still become a valid one, which is the case when no
+    /// letter run is too long and it does not start with a forbidden class.
+    pub fn check_prefix(&self, s: &str) -> bool {
+        self.rules.iter().all(|rule| match rule {
+            PhonotacticRule::MaxRun{class, max} => {
+                let mut run = 0;
+                s.chars().all(|c| {
+                    run = if self.in_class(class, c) { run + 1 } else { 0 };
+                    run <= *max
+                })
+            }
+            PhonotacticRule::NotStart(class) => s.chars().next().map(|c| !self.in_class(class, c)).unwrap_or(true),
+            PhonotacticRule::NotEnd(_) | PhonotacticRule::Require(_) => true,
+        })
+    }
+
+    /// Check a whole name against the rules.
+    pub fn check(&self, s: &str) -> bool {
+        self.check_prefix(s) && self.rules.iter().all(|rule| match rule {
+            PhonotacticRule::NotEnd(class) => s.chars().last().map(|c| !self.in_class(class, c)).unwrap_or(true),
+            PhonotacticRule::Require(class) => s.chars().any(|c| self.in_class(class, c)),
+            PhonotacticRule::MaxRun{..} | PhonotacticRule::NotStart(_) => true,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for rule in self.rules.iter() {
+            let class = rule.class();
+            if class != "vowel" && class != "consonant" && !self.classes.iter().any(|(n, _)| n == class) {
+                return Err(ValidationError::new("Phonotactics", "rule refers to an unknown class."));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn in_class(&self, class: &str, c: char) -> bool {
+        let c = c.to_lowercase().next().unwrap_or(c);
+        if let Some((_, chars)) = self.classes.iter().find(|(n, _)| n == class) {
+            return chars.contains(c);
+        }
+
+        match class {
+            "vowel" => self.is_vowel(c),
+            "consonant" => c.is_alphabetic() && !self.is_vowel(c),
+            _ => false,
+        }
+    }
+
+    fn is_vowel(&self, c: char) -> bool {
+        match self.classes.iter().find(|(n, _)| n == "vowel") {
+            Some((_, chars)) => chars.contains(c),
+            None => VOWELS.contains(c),
+        }
+    }
+
+    pub fn new() -> Phonotactics {
+        Phonotactics{
+            classes: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_rule(PhonotacticRule::parse("max-run:consonant:3").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("not-end:liquid").unwrap());
+        phonotactics.add_rule(PhonotacticRule::parse("require:vowel").unwrap());
+        phonotactics.add_class("liquid", "LR");
+        phonotactics.validate().unwrap();
+
+        assert!(phonotactics.check("Astrid"));
+        assert!(!phonotactics.check("Astrrid"));
+        assert!(!phonotactics.check("Tsk"));
+        assert!(phonotactics.check("Øyvind"));
+        assert!(!phonotactics.check("Ingmar"));
+        assert!(phonotactics.check_prefix("Ingmar"));
+        assert!(!phonotactics.check_prefix("Astrr"));
+
+        phonotactics.add_class("vowel", "aeiou");
+        assert!(!phonotactics.check("Øyvnd"));
+        assert!(phonotactics.check_prefix("Øyv"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(PhonotacticRule::parse("max-run:vowel:2"), Some(PhonotacticRule::MaxRun{class: "vowel".to_owned(), max: 2}));
+        assert_eq!(PhonotacticRule::parse("not-start:consonant"), Some(PhonotacticRule::NotStart("consonant".to_owned())));
+        assert_eq!(PhonotacticRule::parse("max-run:vowel"), None);
+        assert_eq!(PhonotacticRule::parse("max-run:vowel:x"), None);
+        assert_eq!(PhonotacticRule::parse("never:vowel"), None);
+
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_rule(PhonotacticRule::Require("sibilant".to_owned()));
+        assert!(phonotactics.validate().is_err());
+    }
+}
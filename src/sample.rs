@@ -1,3 +1,5 @@
+use crate::normalize::{NormalizationRule, normalize_string};
+
 #[derive(Clone, std::fmt::Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
@@ -7,13 +9,26 @@ pub enum Sample {
     Tokens(Vec<String>),
 }
 
+impl Sample {
+    /// Get a copy of the sample with its text normalized by the rules.
+    pub fn normalized(&self, rules: &[NormalizationRule]) -> Sample {
+        match self {
+            Sample::Word(w) => Sample::Word(normalize_string(w, rules)),
+            Sample::WordWeighted(w, n) => Sample::WordWeighted(normalize_string(w, rules), *n),
+            Sample::Tokens(tokens) => Sample::Tokens(tokens.iter().map(|t| normalize_string(t, rules)).collect()),
+        }
+    }
+}
+
 #[derive(Clone, std::fmt::Debug)]
-#[cfg_attr(target = "wasm32-unknown-unknown", wasm_bindgen)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct SampleSet {
     labels: Vec<String>,
     samples: Vec<Sample>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if="Vec::is_empty"))]
+    normalization: Vec<NormalizationRule>,
 }
 
 impl SampleSet {
@@ -25,14 +40,36 @@ impl SampleSet {
         &self.samples
     }
 
+    pub fn normalization(&self) -> &[NormalizationRule] {
+        &self.normalization
+    }
+
+    /// Add a sample, normalized by the set's normalization rules.
     pub fn add_sample(&mut self, sample: Sample) {
-        self.samples.push(sample);
+        if self.normalization.is_empty() {
+            self.samples.push(sample);
+        } else {
+            self.samples.push(sample.normalized(&self.normalization));
+        }
+    }
+
+    /// Set the normalization rules, which are applied to the samples already in the set and to
+    /// the ones added after. A [`NamePart`](crate::NamePart) with its own normalization applies
+    /// it on top of this.
+    pub fn set_normalization(&mut self, rules: &[NormalizationRule]) {
+        self.normalization = rules.to_vec();
+        if !rules.is_empty() {
+            for sample in self.samples.iter_mut() {
+                *sample = sample.normalized(rules);
+            }
+        }
     }
 
     pub fn new() -> SampleSet {
         SampleSet{
             samples: Vec::new(),
             labels: Vec::new(),
+            normalization: Vec::new(),
         }
     }
 
@@ -40,6 +77,24 @@ impl SampleSet {
         SampleSet{
             samples: Vec::new(),
             labels: labels.iter().map(|s| s.as_ref().to_owned()).collect(),
+            normalization: Vec::new(),
         }
     }
 }
+
+impl Default for SampleSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Make a sample set of plain words, for the generators' tests.
+#[cfg(test)]
+pub(crate) fn sample_set(words: &[&str]) -> SampleSet {
+    let mut sample_set = SampleSet::new();
+    for word in words.iter() {
+        sample_set.add_sample(Sample::Word((*word).to_owned()));
+    }
+
+    sample_set
+}
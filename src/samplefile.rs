@@ -0,0 +1,195 @@
+use crate::{Sample, SampleSet};
+use crate::core::ParseError;
+
+impl SampleSet {
+    /// Parse a block file, which is the format used to feed `CFGrammar` parts. Blocks are separated
+    /// by blank lines, and the first line of each block is the label header. The lines after that
+    /// are samples with one space-separated token per label.
+    ///
+    /// ```text
+    /// # Lines starting with a hash are comments.
+    /// SV * END
+    /// e le na
+    /// i ly na
+    ///
+    /// SCV * END
+    /// ma na va
+    /// ```
+    pub fn parse_blocks(data: &str) -> Result<Vec<SampleSet>, ParseError> {
+        let mut sets: Vec<SampleSet> = Vec::new();
+        let mut new_set = true;
+
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                new_set = true;
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if new_set {
+                sets.push(SampleSet::with_labels(&tokens));
+                new_set = false;
+            } else {
+                let set = sets.last_mut().unwrap();
+                if tokens.len() != set.labels().len() {
+                    return Err(ParseError::new(
+                        i + 1,
+                        format!("Expected {} tokens, found {}", set.labels().len(), tokens.len()),
+                    ));
+                }
+
+                set.add_sample(Sample::Tokens(tokens.iter().map(|t| (*t).to_owned()).collect()));
+            }
+        }
+
+        Ok(sets)
+    }
+
+    /// Parse a word list, which is the format used to feed `Markov` and `WordList` parts. Every
+    /// line is a word, optionally followed by a weight. A weighted line becomes a
+    /// `Sample::WordWeighted`, while the rest become `Sample::Word`.
+    ///
+    /// ```text
+    /// # Lines starting with a hash are comments.
+    /// aeris
+    /// baldur 4
+    /// ```
+    pub fn parse_words(data: &str) -> Result<SampleSet, ParseError> {
+        let mut set = SampleSet::new();
+
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let word = fields.next().unwrap();
+            let weight = fields.next();
+            if fields.next().is_some() {
+                return Err(ParseError::new(i + 1, "Expected a word and an optional weight".to_owned()));
+            }
+
+            match weight {
+                Some(weight) => match weight.parse::<u32>() {
+                    Ok(weight) if weight > 0 => set.add_sample(Sample::WordWeighted(word.to_owned(), weight)),
+                    _ => return Err(ParseError::new(i + 1, format!("Invalid weight: {}", weight))),
+                },
+                None => set.add_sample(Sample::Word(word.to_owned())),
+            }
+        }
+
+        Ok(set)
+    }
+
+    /// Write sample sets in the block format read by [`SampleSet::parse_blocks`]. Sets without
+    /// labels get a header of anonymous labels. Only `Sample::Tokens` survive a round-trip, word
+    /// samples are written as single-token lines.
+    pub fn blocks_to_string(sets: &[SampleSet]) -> String {
+        let mut res = String::with_capacity(256);
+
+        for (i, set) in sets.iter().enumerate() {
+            if i > 0 {
+                res.push('\n');
+            }
+
+            if set.labels().is_empty() {
+                let len = match set.samples().first() {
+                    Some(Sample::Tokens(tokens)) => tokens.len(),
+                    _ => 1,
+                };
+                res.push_str(&vec!["*"; len].join(" "));
+            } else {
+                res.push_str(&set.labels().join(" "));
+            }
+            res.push('\n');
+
+            for sample in set.samples() {
+                match sample {
+                    Sample::Tokens(tokens) => res.push_str(&tokens.join(" ")),
+                    Sample::Word(w) | Sample::WordWeighted(w, _) => res.push_str(w),
+                }
+                res.push('\n');
+            }
+        }
+
+        res
+    }
+
+    /// Write the samples in the word list format read by [`SampleSet::parse_words`]. Token samples
+    /// are joined into one word.
+    pub fn to_words_string(&self) -> String {
+        let mut res = String::with_capacity(256);
+
+        for sample in self.samples() {
+            match sample {
+                Sample::Word(w) => res.push_str(w),
+                Sample::WordWeighted(w, n) => {
+                    res.push_str(w);
+                    res.push(' ');
+                    res.push_str(&n.to_string());
+                }
+                Sample::Tokens(tokens) => res.push_str(&tokens.concat()),
+            }
+            res.push('\n');
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blocks() {
+        let sets = SampleSet::parse_blocks("# comment\nSV * END\ne le na\ni ly na\n\n\nSCV * END\n  ma na  va\n").unwrap();
+
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].labels(), &["SV", "*", "END"]);
+        assert_eq!(sets[0].samples().len(), 2);
+        assert_eq!(sets[1].labels(), &["SCV", "*", "END"]);
+        assert_eq!(sample_strs(&sets[1]), vec!["ma na va"]);
+    }
+
+    #[test]
+    fn test_parse_blocks_error() {
+        let err = SampleSet::parse_blocks("SV * END\ne le na\n\nSCV END\nma na va\n").unwrap_err();
+
+        assert_eq!(err.line(), 5);
+    }
+
+    #[test]
+    fn test_parse_words() {
+        let set = SampleSet::parse_words("aeris\n\n# comment\nbaldur 4\n").unwrap();
+
+        assert_eq!(sample_strs(&set), vec!["aeris", "baldur*4"]);
+        assert_eq!(SampleSet::parse_words("aeris\nbaldur 0").unwrap_err().line(), 2);
+        assert_eq!(SampleSet::parse_words("aeris x").unwrap_err().line(), 1);
+        assert_eq!(SampleSet::parse_words("aeris 2 3").unwrap_err().line(), 1);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let blocks = "SV * END\ne le na\ni ly na\n\nSCV * END\nma na va\n";
+        let sets = SampleSet::parse_blocks(blocks).unwrap();
+        assert_eq!(SampleSet::blocks_to_string(&sets), blocks);
+
+        let words = "aeris\nbaldur 4\n";
+        let set = SampleSet::parse_words(words).unwrap();
+        assert_eq!(set.to_words_string(), words);
+    }
+
+    fn sample_strs(set: &SampleSet) -> Vec<String> {
+        set.samples().iter().map(|s| match s {
+            Sample::Word(w) => w.clone(),
+            Sample::WordWeighted(w, n) => format!("{}*{}", w, n),
+            Sample::Tokens(tokens) => tokens.join(" "),
+        }).collect()
+    }
+}
@@ -0,0 +1,591 @@
+use rand::Rng;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::{LearnError, WorkingSet, Sample, SampleSet, Phonotactics};
+use crate::core::{ValidationError, GenerateError, DEFAULT_MAX_ATTEMPTS};
+use crate::phonotactics::VOWELS;
+
+/// The shape of a syllable, where `C` is the onset or coda, and `V` is the nucleus.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SyllableTemplate {
+    V,
+    CV,
+    VC,
+    CVC,
+}
+
+impl SyllableTemplate {
+    /// Parse a template from `V`, `CV`, `VC` or `CVC`.
+    pub fn parse(s: &str) -> Option<SyllableTemplate> {
+        match s {
+            "V" => Some(SyllableTemplate::V),
+            "CV" => Some(SyllableTemplate::CV),
+            "VC" => Some(SyllableTemplate::VC),
+            "CVC" => Some(SyllableTemplate::CVC),
+            _ => None,
+        }
+    }
+
+    pub fn has_onset(self) -> bool {
+        self == SyllableTemplate::CV || self == SyllableTemplate::CVC
+    }
+
+    pub fn has_coda(self) -> bool {
+        self == SyllableTemplate::VC || self == SyllableTemplate::CVC
+    }
+
+    fn with_parts(onset: bool, coda: bool) -> SyllableTemplate {
+        match (onset, coda) {
+            (false, false) => SyllableTemplate::V,
+            (true, false) => SyllableTemplate::CV,
+            (false, true) => SyllableTemplate::VC,
+            (true, true) => SyllableTemplate::CVC,
+        }
+    }
+}
+
+/// The Syllable generator builds names from weighted syllable templates, onsets, nuclei and
+/// codas, which is how many conlangs are designed. It can be written by hand with the `add_*`
+/// functions, or learned from samples, which are split into syllables around their vowels.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Syllable {
+    templates: Weighted<SyllableTemplate>,
+    onsets: Weighted<String>,
+    nuclei: Weighted<String>,
+    codas: Weighted<String>,
+    lengths: Weighted<usize>,
+    vowels: String,
+}
+
+impl Syllable {
+    /// Generate a name. You need to provide your own WorkingSet and Rng, which is necessary to save
+    /// on allocations. A dependent application should use the full name generator interface instead
+    pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
+        if self.try_generate(ws, rng, DEFAULT_MAX_ATTEMPTS).is_err() {
+            ws.result_str.clear();
+        }
+    }
+
+    /// Generate a name like `generate`, but return an error if nothing is learned, or if no name
+    /// is found after `max_attempts` tries.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize) -> Result<(), GenerateError> {
+        self.try_generate_with(ws, rng, max_attempts, &Phonotactics::new())
+    }
+
+    /// Generate a name like `try_generate` that follows the phonotactic rules. The name is checked
+    /// after each syllable, and it starts over as soon as a rule is broken.
+    pub fn try_generate_with(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
+        if self.lengths.total == 0 || self.templates.total == 0 || self.nuclei.total == 0 {
+            return Err(GenerateError::new("parts::Syllable", "Nothing has been learned."));
+        }
+
+        'attempts: for _ in 0..max_attempts {
+            ws.result_str.clear();
+
+            let length = *self.lengths.pick(rng).unwrap();
+            for _ in 0..length {
+                let template = *self.templates.pick(rng).unwrap();
+                if template.has_onset() {
+                    match self.onsets.pick(rng) {
+                        Some(onset) => ws.result_str.push_str(onset),
+                        None => continue 'attempts,
+                    }
+                }
+                ws.result_str.push_str(self.nuclei.pick(rng).unwrap());
+                if template.has_coda() {
+                    match self.codas.pick(rng) {
+                        Some(coda) => ws.result_str.push_str(coda),
+                        None => continue 'attempts,
+                    }
+                }
+
+                if !phonotactics.check_prefix(&ws.result_str) {
+                    continue 'attempts;
+                }
+            }
+
+            if phonotactics.check(&ws.result_str) {
+                return Ok(());
+            }
+        }
+
+        Err(GenerateError::new("parts::Syllable", "No valid output found within the attempt budget."))
+    }
+
+    /// Get the probability of generating the string, or `None` if it can't be generated at all.
+    /// Every way of splitting it into syllables counts, so this is exact.
+    pub fn score(&self, s: &str) -> Option<f64> {
+        let max_length = self.lengths.items.iter().map(|(l, _)| *l).max()?;
+
+        // The probability of generating the first `pos` bytes with `k` syllables.
+        let mut prefixes = vec![vec![0.0; s.len() + 1]; max_length + 1];
+        prefixes[0][0] = 1.0;
+        for k in 0..max_length {
+            for pos in 0..s.len() {
+                let p = prefixes[k][pos];
+                if p == 0.0 {
+                    continue;
+                }
+
+                for (len, syllable_p) in self.score_syllable(&s[pos..]) {
+                    prefixes[k + 1][pos + len] += p * syllable_p;
+                }
+            }
+        }
+
+        let score: f64 = self.lengths.items.iter()
+            .map(|(length, weight)| self.lengths.probability(*weight) * prefixes[*length][s.len()])
+            .sum();
+
+        if score > 0.0 { Some(score) } else { None }
+    }
+
+    /// Get the length and probability of every syllable that the string can start with.
+    fn score_syllable(&self, s: &str) -> Vec<(usize, f64)> {
+        let mut res = Vec::new();
+
+        for (template, template_weight) in self.templates.items.iter() {
+            let p = self.templates.probability(*template_weight);
+
+            for (onset_len, onset_p) in self.score_part(&self.onsets, template.has_onset(), s) {
+                for (nucleus, nucleus_weight) in self.nuclei.items.iter() {
+                    if !s[onset_len..].starts_with(nucleus.as_str()) {
+                        continue;
+                    }
+                    let pos = onset_len + nucleus.len();
+                    let p = p * onset_p * self.nuclei.probability(*nucleus_weight);
+
+                    for (coda_len, coda_p) in self.score_part(&self.codas, template.has_coda(), &s[pos..]) {
+                        res.push((pos + coda_len, p * coda_p));
+                    }
+                }
+            }
+        }
+
+        res
+    }
+
+    fn score_part(&self, set: &Weighted<String>, used: bool, s: &str) -> Vec<(usize, f64)> {
+        if !used {
+            return vec![(0, 1.0)];
+        }
+
+        set.items.iter()
+            .filter(|(item, _)| s.starts_with(item.as_str()))
+            .map(|(item, weight)| (item.len(), set.probability(*weight)))
+            .collect()
+    }
+
+    /// Learn the syllables of word samples. A sample is split at its runs of vowels, which become
+    /// the nuclei. The consonants before the first and after the last one become the first onset
+    /// and last coda, and the ones between two nuclei are split with the larger half going to the
+    /// onset. The samples must have at least one vowel.
+    pub fn learn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let old_state = self.clone();
+        for sample in sample_set.samples() {
+            if let Err(err) = self.learn_one(sample) {
+                *self = old_state;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn learn_one(&mut self, sample: &Sample) -> Result<(), LearnError> {
+        let (word, weight) = sample_word(sample)?;
+        let syllables = self.syllabify(word)
+            .ok_or_else(|| LearnError::new(0, "At least one vowel required".to_owned(), Some(sample.clone())))?;
+
+        let onsets = syllables.iter().filter(|(onset, _, _)| !onset.is_empty()).count();
+        let codas = syllables.iter().filter(|(_, _, coda)| !coda.is_empty()).count();
+        if !self.templates.has_room(weight, syllables.len()) || !self.onsets.has_room(weight, onsets)
+            || !self.nuclei.has_room(weight, syllables.len()) || !self.codas.has_room(weight, codas)
+            || !self.lengths.has_room(weight, 1)
+        {
+            return Err(LearnError::new(7, format!("Weights add up to more than {}", u32::MAX), Some(sample.clone())));
+        }
+
+        for (onset, nucleus, coda) in syllables.iter() {
+            self.templates.add(SyllableTemplate::with_parts(!onset.is_empty(), !coda.is_empty()), weight);
+            if !onset.is_empty() {
+                self.onsets.add((*onset).to_owned(), weight);
+            }
+            self.nuclei.add((*nucleus).to_owned(), weight);
+            if !coda.is_empty() {
+                self.codas.add((*coda).to_owned(), weight);
+            }
+        }
+        self.lengths.add(syllables.len(), weight);
+
+        Ok(())
+    }
+
+    /// Forget samples that were learned before. If one of them wasn't, this returns an error and
+    /// leaves the generator unchanged.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let old_state = self.clone();
+        for sample in sample_set.samples() {
+            if let Err(err) = self.unlearn_one(sample) {
+                *self = old_state;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unlearn_one(&mut self, sample: &Sample) -> Result<(), LearnError> {
+        let (word, weight) = sample_word(sample)?;
+        let not_learned = || LearnError::new(5, "Sample has not been learned".to_owned(), Some(sample.clone()));
+        let syllables = self.syllabify(word).ok_or_else(not_learned)?;
+
+        let mut ok = self.lengths.remove(&syllables.len(), weight);
+        for (onset, nucleus, coda) in syllables.iter() {
+            ok &= self.templates.remove(&SyllableTemplate::with_parts(!onset.is_empty(), !coda.is_empty()), weight);
+            ok &= onset.is_empty() || self.onsets.remove(&(*onset).to_owned(), weight);
+            ok &= self.nuclei.remove(&(*nucleus).to_owned(), weight);
+            ok &= coda.is_empty() || self.codas.remove(&(*coda).to_owned(), weight);
+        }
+
+        if ok { Ok(()) } else { Err(not_learned()) }
+    }
+
+    /// Split a word into (onset, nucleus, coda) syllables, or `None` if it has no vowels.
+    fn syllabify<'a>(&self, word: &'a str) -> Option<Vec<(&'a str, &'a str, &'a str)>> {
+        // Runs of vowels or consonants, as the byte offsets of their grapheme clusters and the end.
+        let mut runs: Vec<(bool, Vec<usize>)> = Vec::new();
+        for (pos, grapheme) in word.grapheme_indices(true) {
+            let first = grapheme.chars().next().unwrap();
+            let vowel = first.to_lowercase().any(|c| self.vowels.contains(c));
+            let end = pos + grapheme.len();
+
+            match runs.last_mut() {
+                Some((run_vowel, bounds)) if *run_vowel == vowel => bounds.push(end),
+                _ => runs.push((vowel, vec![pos, end])),
+            }
+        }
+
+        let nuclei: Vec<usize> = runs.iter().enumerate().filter(|(_, (v, _))| *v).map(|(i, _)| i).collect();
+        if nuclei.is_empty() {
+            return None;
+        }
+
+        // The split point of the consonants before a run: all of them for the first nucleus, and
+        // the larger half for the others.
+        let onset_start = |i: usize, first: bool| -> usize {
+            let bounds = &runs[i - 1].1;
+            if first { bounds[0] } else { bounds[(bounds.len() - 1) / 2] }
+        };
+
+        let mut syllables = Vec::with_capacity(nuclei.len());
+        for (k, i) in nuclei.iter().cloned().enumerate() {
+            let bounds = &runs[i].1;
+            let (start, end) = (bounds[0], *bounds.last().unwrap());
+
+            let onset = if i > 0 { &word[onset_start(i, k == 0)..start] } else { "" };
+            let coda = if i + 1 < runs.len() {
+                let next = if k + 1 == nuclei.len() { word.len() } else { onset_start(i + 2, false) };
+                &word[end..next]
+            } else {
+                ""
+            };
+
+            syllables.push((onset, &word[start..end], coda));
+        }
+
+        Some(syllables)
+    }
+
+    /// Add a weight to a template. Like the other `add_` functions, this returns false and
+    /// changes nothing if the weights would add up to more than `u32::MAX`.
+    pub fn add_template(&mut self, template: SyllableTemplate, weight: u32) -> bool {
+        self.templates.add(template, weight)
+    }
+
+    pub fn add_onset(&mut self, onset: &str, weight: u32) -> bool {
+        self.onsets.add(onset.to_owned(), weight)
+    }
+
+    pub fn add_nucleus(&mut self, nucleus: &str, weight: u32) -> bool {
+        self.nuclei.add(nucleus.to_owned(), weight)
+    }
+
+    pub fn add_coda(&mut self, coda: &str, weight: u32) -> bool {
+        self.codas.add(coda.to_owned(), weight)
+    }
+
+    /// Add a weight to names of this many syllables.
+    pub fn add_length(&mut self, syllables: usize, weight: u32) -> bool {
+        self.lengths.add(syllables, weight)
+    }
+
+    pub fn vowels(&self) -> &str {
+        &self.vowels
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !self.templates.is_valid() || !self.onsets.is_valid() || !self.nuclei.is_valid()
+            || !self.codas.is_valid() || !self.lengths.is_valid() {
+            return Err(ValidationError::new("parts::Syllable", "incorrect weights."));
+        }
+        if [&self.onsets, &self.nuclei, &self.codas].iter().any(|set| set.items.iter().any(|(s, _)| s.is_empty())) {
+            return Err(ValidationError::new("parts::Syllable", "empty onset, nucleus or coda."));
+        }
+        if self.lengths.items.iter().any(|(l, _)| *l == 0) {
+            return Err(ValidationError::new("parts::Syllable", "length of zero syllables."));
+        }
+
+        let totals = [self.templates.total, self.nuclei.total, self.lengths.total];
+        if totals.iter().any(|t| *t > 0) && (self.templates.total == 0 || self.nuclei.total == 0 || self.lengths.total == 0) {
+            return Err(ValidationError::new("parts::Syllable", "templates, nuclei and lengths must all be set."));
+        }
+        if self.templates.items.iter().any(|(t, _)| t.has_onset()) && self.onsets.total == 0 {
+            return Err(ValidationError::new("parts::Syllable", "template with onset, but no onsets."));
+        }
+        if self.templates.items.iter().any(|(t, _)| t.has_coda()) && self.codas.total == 0 {
+            return Err(ValidationError::new("parts::Syllable", "template with coda, but no codas."));
+        }
+
+        Ok(())
+    }
+
+    /// Create a generator that uses the default vowels when learning, which covers the Latin
+    /// alphabet with its common diacritics.
+    pub fn new() -> Syllable {
+        Self::with_vowels(VOWELS)
+    }
+
+    /// Create a generator with the letters that are vowels when learning.
+    pub fn with_vowels(vowels: &str) -> Syllable {
+        Syllable{
+            templates: Weighted::new(),
+            onsets: Weighted::new(),
+            nuclei: Weighted::new(),
+            codas: Weighted::new(),
+            lengths: Weighted::new(),
+            vowels: vowels.chars().flat_map(|c| c.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl Default for Syllable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sample_word(sample: &Sample) -> Result<(&str, u32), LearnError> {
+    match sample {
+        Sample::Word(w) => Ok((w, 1)),
+        Sample::WordWeighted(w, weight) => Ok((w, *weight)),
+        _ => Err(LearnError::new(
+            1,
+            "Incorrect sample type. Must be Word".to_owned(),
+            Some(sample.clone()),
+        )),
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Weighted<T> {
+    items: Vec<(T, u32)>,
+    total: u32,
+}
+
+impl<T: PartialEq> Weighted<T> {
+    /// Add weight to an item. This returns false and changes nothing if a weight would overflow,
+    /// which `has_room` can check for beforehand.
+    fn add(&mut self, item: T, weight: u32) -> bool {
+        let total = match self.total.checked_add(weight) {
+            Some(total) => total,
+            None => return false,
+        };
+        match self.items.iter_mut().find(|(i, _)| *i == item) {
+            Some((_, w)) => match w.checked_add(weight) {
+                Some(sum) => *w = sum,
+                None => return false,
+            },
+            None => self.items.push((item, weight)),
+        }
+        self.total = total;
+
+        true
+    }
+
+    /// Check if the weight can be added this many times without overflowing the total, which
+    /// means no item's weight overflows either.
+    fn has_room(&self, weight: u32, times: usize) -> bool {
+        (weight as u64).checked_mul(times as u64)
+            .and_then(|added| added.checked_add(self.total as u64))
+            .is_some_and(|total| total <= u32::MAX as u64)
+    }
+
+    /// Remove weight from an item, and the item itself if nothing is left. This returns false
+    /// if it doesn't have that much weight.
+    fn remove(&mut self, item: &T, weight: u32) -> bool {
+        let index = match self.items.iter().position(|(i, w)| i == item && *w >= weight) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.items[index].1 -= weight;
+        if self.items[index].1 == 0 {
+            self.items.remove(index);
+        }
+        self.total -= weight;
+
+        true
+    }
+
+    fn pick(&self, rng: &mut impl Rng) -> Option<&T> {
+        if self.total == 0 {
+            return None;
+        }
+
+        let mut roll = rng.gen_range(0, self.total);
+        for (item, weight) in self.items.iter() {
+            if roll < *weight {
+                return Some(item);
+            }
+            roll -= *weight;
+        }
+
+        None
+    }
+
+    fn probability(&self, weight: u32) -> f64 {
+        weight as f64 / self.total as f64
+    }
+
+    fn is_valid(&self) -> bool {
+        self.items.iter().all(|(_, w)| *w > 0)
+            && self.items.iter().try_fold(0u32, |total, (_, w)| total.checked_add(*w)) == Some(self.total)
+    }
+
+    fn new() -> Weighted<T> {
+        Weighted{
+            items: Vec::new(),
+            total: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+    use crate::sample::sample_set;
+
+    #[test]
+    fn test_syllabify() {
+        let syllable = Syllable::new();
+
+        assert_eq!(syllable.syllabify("astrid").unwrap(), vec![("", "a", "s"), ("tr", "i", "d")]);
+        assert_eq!(syllable.syllabify("halvard").unwrap(), vec![("h", "a", "l"), ("v", "a", "rd")]);
+        assert_eq!(syllable.syllabify("kaito").unwrap(), vec![("k", "ai", ""), ("t", "o", "")]);
+        assert_eq!(syllable.syllabify("bjørn").unwrap(), vec![("bj", "ø", "rn")]);
+        assert_eq!(syllable.syllabify("to\u{304}ru").unwrap(), vec![("t", "o\u{304}", ""), ("r", "u", "")]);
+        assert_eq!(syllable.syllabify("tsk"), None);
+    }
+
+    #[test]
+    fn test_learn() {
+        let mut syllable = Syllable::new();
+        syllable.learn(&sample_set(&["kaito", "haruka", "sora", "akira", "yuna"])).unwrap();
+        syllable.validate().unwrap();
+
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(9);
+        for _ in 0..100 {
+            syllable.try_generate(&mut ws, &mut rng, 100).unwrap();
+            assert!(syllable.score(&ws.result_str).unwrap() > 0.0, "{}", ws.result_str);
+        }
+
+        assert!(syllable.score("haru").unwrap() > syllable.score("harukaito").unwrap_or(0.0));
+        assert_eq!(syllable.score("hxru"), None);
+        assert!(syllable.learn(&sample_set(&["mimo", "tsk"])).is_err());
+        assert_eq!(syllable.score("mimo"), None);
+    }
+
+    #[test]
+    fn test_weight_overflow() {
+        let weighted = |samples: &[(&str, u32)]| {
+            let mut sample_set = SampleSet::new();
+            for (word, weight) in samples.iter() {
+                sample_set.add_sample(Sample::WordWeighted((*word).to_owned(), *weight));
+            }
+            sample_set
+        };
+
+        let mut syllable = Syllable::new();
+        assert!(syllable.learn(&weighted(&[("ka", u32::MAX), ("ka", 1)])).is_err());
+        assert_eq!(syllable.score("ka"), None);
+
+        syllable.learn(&weighted(&[("ka", u32::MAX)])).unwrap();
+        let err = syllable.learn(&weighted(&[("ka", 1)])).unwrap_err();
+        assert!(err.to_string().contains("more than 4294967295"), "{}", err);
+        syllable.validate().unwrap();
+        assert!((syllable.score("ka").unwrap() - 1.0).abs() < 1e-9);
+
+        // A sample with more syllables adds its weight once for each of them.
+        let mut syllable = Syllable::new();
+        assert!(syllable.learn(&weighted(&[("kaka", u32::MAX / 2 + 1)])).is_err());
+        assert!(syllable.add_nucleus("a", u32::MAX));
+        assert!(!syllable.add_nucleus("e", 1));
+    }
+
+    #[test]
+    fn test_hand_authored() {
+        let mut syllable = Syllable::new();
+        assert!(syllable.try_generate(&mut WorkingSet::new(), &mut SmallRng::seed_from_u64(1), 10).is_err());
+
+        syllable.add_template(SyllableTemplate::CV, 3);
+        syllable.add_template(SyllableTemplate::CVC, 1);
+        syllable.add_onset("k", 1);
+        syllable.add_onset("th", 1);
+        syllable.add_nucleus("a", 2);
+        syllable.add_nucleus("e", 1);
+        syllable.add_length(1, 1);
+        syllable.add_length(2, 1);
+        assert!(syllable.validate().is_err());
+
+        syllable.add_coda("n", 1);
+        syllable.validate().unwrap();
+
+        // CV is 3/4 and k is 1/2, so ka is 3/4 * 1/2 * 2/3 out of the 1/2 for one syllable.
+        assert!((syllable.score("ka").unwrap() - 0.125).abs() < 1e-9);
+
+        let mut ws = WorkingSet::new();
+        let mut rng = SmallRng::seed_from_u64(5);
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..20000 {
+            syllable.generate(&mut ws, &mut rng);
+            *counts.entry(ws.result_str.clone()).or_insert(0) += 1;
+        }
+        for (name, count) in counts.iter() {
+            let score = syllable.score(name).unwrap();
+            assert!((score - *count as f64 / 20000.0).abs() < 0.01, "{} {} {}", name, score, count);
+        }
+    }
+
+    #[test]
+    fn test_unlearn() {
+        let mut syllable = Syllable::new();
+        syllable.learn(&sample_set(&["kaito", "haruka", "sora"])).unwrap();
+        let before = syllable.score("sora").unwrap();
+
+        syllable.learn(&sample_set(&["akira"])).unwrap();
+        syllable.unlearn(&sample_set(&["akira"])).unwrap();
+        assert!((syllable.score("sora").unwrap() - before).abs() < 1e-9);
+        assert!(syllable.unlearn(&sample_set(&["akira"])).is_err());
+        assert!(syllable.unlearn(&sample_set(&["sora", "sora"])).is_err());
+
+        syllable.unlearn(&sample_set(&["kaito", "haruka", "sora"])).unwrap();
+        syllable.validate().unwrap();
+        assert!(syllable.try_generate(&mut WorkingSet::new(), &mut SmallRng::seed_from_u64(1), 10).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A trie over a generator's tokens that finds the longest token at the start of a string. The
+/// generators only ever add tokens, so the trie catches up with `sync` instead of being rebuilt,
+/// and it isn't serialized since it's rebuilt from the token list.
+///
+/// Tokens only match whole grapheme clusters, so a token never splits a letter from its
+/// combining marks.
+#[derive(Clone, Default)]
+pub(crate) struct Tokenizer {
+    nodes: Vec<TrieNode>,
+    len: usize,
+}
+
+#[derive(Clone, Default)]
+struct TrieNode {
+    children: Vec<(char, usize)>,
+    token: Option<usize>,
+}
+
+impl Tokenizer {
+    /// Find the longest token that the string starts with, and return its index and its length
+    /// in bytes.
+    pub fn longest_match(&self, s: &str) -> Option<(usize, usize)> {
+        let mut current = 0;
+        let mut best = None;
+
+        'outer: for (pos, grapheme) in s.grapheme_indices(true) {
+            for ch in grapheme.chars() {
+                let node = match self.nodes.get(current) {
+                    Some(node) => node,
+                    None => break 'outer,
+                };
+                current = match node.children.iter().find(|(c, _)| *c == ch) {
+                    Some((_, child)) => *child,
+                    None => break 'outer,
+                };
+            }
+            if let Some(token) = self.nodes[current].token {
+                best = Some((token, pos + grapheme.len()));
+            }
+        }
+
+        best
+    }
+
+    /// Add the tokens that were pushed to the list since the last sync.
+    pub fn sync<S: AsRef<str>>(&mut self, tokens: &[S]) {
+        for (i, token) in tokens.iter().enumerate().skip(self.len) {
+            self.insert(token.as_ref(), i);
+        }
+        self.len = self.len.max(tokens.len());
+    }
+
+    fn insert(&mut self, token: &str, index: usize) {
+        if token.is_empty() {
+            return;
+        }
+        if self.nodes.is_empty() {
+            self.nodes.push(TrieNode::default());
+        }
+
+        let mut current = 0;
+        for ch in token.chars() {
+            current = match self.nodes[current].children.iter().find(|(c, _)| *c == ch) {
+                Some((_, child)) => *child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[current].children.push((ch, child));
+                    child
+                }
+            };
+        }
+
+        // A duplicate keeps the index of the first one.
+        if self.nodes[current].token.is_none() {
+            self.nodes[current].token = Some(index);
+        }
+    }
+
+    pub fn new<S: AsRef<str>>(tokens: &[S]) -> Tokenizer {
+        let mut tokenizer = Tokenizer::default();
+        tokenizer.sync(tokens);
+
+        tokenizer
+    }
+}
+
+/// Get the first grapheme cluster of a non-empty string, which is what the generators use as a
+/// new token when none of the existing ones match.
+pub(crate) fn first_grapheme(s: &str) -> &str {
+    s.graphemes(true).next().unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_match() {
+        let tokenizer = Tokenizer::new(&["t", "th", "ts", "tsy", "ø", "øy", "t"]);
+
+        assert_eq!(tokenizer.longest_match("thor"), Some((1, 2)));
+        assert_eq!(tokenizer.longest_match("tsyra"), Some((3, 3)));
+        assert_eq!(tokenizer.longest_match("tsar"), Some((2, 2)));
+        assert_eq!(tokenizer.longest_match("tyr"), Some((0, 1)));
+        assert_eq!(tokenizer.longest_match("øyvind"), Some((5, 3)));
+        assert_eq!(tokenizer.longest_match("ødegård"), Some((4, 2)));
+        assert_eq!(tokenizer.longest_match("arne"), None);
+        assert_eq!(tokenizer.longest_match(""), None);
+    }
+
+    #[test]
+    fn test_clusters() {
+        let tokenizer = Tokenizer::new(&["o", "to", "o\u{304}"]);
+
+        assert_eq!(tokenizer.longest_match("ota"), Some((0, 1)));
+        assert_eq!(tokenizer.longest_match("o\u{304}ta"), Some((2, 3)));
+        assert_eq!(tokenizer.longest_match("to\u{304}"), None);
+        assert_eq!(tokenizer.longest_match("o\u{301}"), None);
+
+        assert_eq!(first_grapheme("o\u{304}ta"), "o\u{304}");
+        assert_eq!(first_grapheme("ørn"), "ø");
+        assert_eq!(first_grapheme("Вера"), "В");
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut tokens = vec!["a".to_owned(), "".to_owned()];
+        let mut tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.longest_match("ae"), None);
+
+        tokenizer.sync(&tokens);
+        assert_eq!(tokenizer.longest_match("ae"), Some((0, 1)));
+
+        tokens.push("ae".to_owned());
+        tokenizer.sync(&tokens);
+        assert_eq!(tokenizer.longest_match("ae"), Some((2, 2)));
+    }
+}
@@ -0,0 +1,332 @@
+//! JavaScript bindings, built with the `wasm` feature. The classes are exported as `Name`,
+//! `NamePart` and `SampleSet`, and errors are thrown as strings.
+//!
+//! ```js
+//! const part = NamePart.markov("first", ["th", "ae"], ["capitalize-first"], false, false, true, true);
+//! const name = new Name();
+//! name.addPart(part);
+//! name.learn("first", SampleSet.parseWords("aeris\nbaldur\ncelia\n"));
+//! name.addFormat("full", "{first}");
+//! console.log(name.generate("full", 10));
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Name, NamePart, Sample, SampleSet, FormattingRule, NormalizationRule, Phonotactics, PhonotacticRule, Syllable, Pattern};
+
+#[wasm_bindgen(js_name = Name)]
+pub struct WasmName {
+    name: Name,
+}
+
+#[wasm_bindgen(js_class = Name)]
+impl WasmName {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmName {
+        WasmName{name: Name::new()}
+    }
+
+    /// Load a generator saved with `toJson`, and check that it's valid.
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(json: &str) -> Result<WasmName, String> {
+        let name: Name = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        name.validate().map_err(|e| e.to_string())?;
+
+        Ok(WasmName{name})
+    }
+
+    /// Build and train a generator from a definition, without sample files.
+    #[wasm_bindgen(js_name = fromDefinition)]
+    pub fn from_definition(data: &str) -> Result<WasmName, String> {
+        Name::from_definition(data)
+            .map(|name| WasmName{name})
+            .map_err(|e| e.to_string())
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(&self.name).map_err(|e| e.to_string())
+    }
+
+    /// Add a copy of the part, which must have a name that isn't used by another part.
+    #[wasm_bindgen(js_name = addPart)]
+    pub fn add_part(&mut self, part: &WasmNamePart) -> Result<(), String> {
+        if self.name.has_part_name(part.part.name()) {
+            return Err(format!("Part {} already exists", part.part.name()));
+        }
+
+        self.name.add_part(part.part.clone());
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = addFormat)]
+    pub fn add_format(&mut self, name: &str, format: &str) -> Result<(), String> {
+        if self.name.has_format_name(name) {
+            return Err(format!("Format {} already exists", name));
+        }
+
+        self.name.add_format(name, format)
+            .map_err(|e| format!("Invalid format: {}\n{}", e.message(), e.highlight(format)))
+    }
+
+    pub fn learn(&mut self, part_name: &str, samples: &WasmSampleSet) -> Result<(), String> {
+        self.name.learn(part_name, &samples.sample_set).map_err(|e| e.to_string())
+    }
+
+    pub fn unlearn(&mut self, part_name: &str, samples: &WasmSampleSet) -> Result<(), String> {
+        self.name.unlearn(part_name, &samples.sample_set).map_err(|e| e.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.name.validate().map_err(|e| e.to_string())
+    }
+
+    pub fn score(&self, name: &str) -> Option<f64> {
+        self.name.score(name)
+    }
+
+    #[wasm_bindgen(js_name = partNames)]
+    pub fn part_names(&self) -> Vec<String> {
+        self.name.parts().map(|p| p.name().to_owned()).collect()
+    }
+
+    #[wasm_bindgen(js_name = formatNames)]
+    pub fn format_names(&self) -> Vec<String> {
+        self.name.formats().map(|f| f.name().to_owned()).collect()
+    }
+
+    /// Generate a batch of names from a format.
+    pub fn generate(&self, format_name: &str, count: usize) -> Result<Vec<String>, String> {
+        collect(self.name.generate(format_name), format_name, count)
+    }
+
+    /// Generate a batch of names from a format, which is the same every time for the same seed.
+    #[wasm_bindgen(js_name = generateSeeded)]
+    pub fn generate_seeded(&self, format_name: &str, count: usize, seed: u32) -> Result<Vec<String>, String> {
+        collect(self.name.generate_seeded(seed as u64, format_name), format_name, count)
+    }
+
+    /// Generate a batch of names from a single part.
+    #[wasm_bindgen(js_name = generatePart)]
+    pub fn generate_part(&self, part_name: &str, count: usize) -> Result<Vec<String>, String> {
+        collect(self.name.generate_part(part_name), part_name, count)
+    }
+}
+
+impl Default for WasmName {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn collect<R: rand::Rng>(iter: Option<crate::GeneratorIter<'_, R>>, name: &str, count: usize) -> Result<Vec<String>, String> {
+    let mut iter = iter.ok_or_else(|| format!("{} not found", name))?;
+
+    let mut res = Vec::with_capacity(count);
+    while res.len() < count {
+        match iter.try_next() {
+            Ok(Some(name)) => res.push(name),
+            Ok(None) => break,
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+
+    Ok(res)
+}
+
+#[wasm_bindgen(js_name = NamePart)]
+pub struct WasmNamePart {
+    part: NamePart,
+}
+
+#[wasm_bindgen(js_class = NamePart)]
+impl WasmNamePart {
+    pub fn markov(name: &str, tokens: Vec<String>, rules: Vec<String>, lrs: bool, lrm: bool, lre: bool, rtf: bool) -> Result<WasmNamePart, String> {
+        let rules = parse_rules(&rules)?;
+        Ok(WasmNamePart{part: NamePart::new_markov(name, &rules, &tokens, lrs, lrm, lre, rtf)})
+    }
+
+    pub fn cfgrammar(name: &str, subtokens: Vec<String>, rules: Vec<String>, rlf: bool, ral: bool) -> Result<WasmNamePart, String> {
+        let rules = parse_rules(&rules)?;
+        Ok(WasmNamePart{part: NamePart::new_cfgrammar(name, &rules, &subtokens, rlf, ral)})
+    }
+
+    pub fn wordlist(name: &str, rules: Vec<String>) -> Result<WasmNamePart, String> {
+        let rules = parse_rules(&rules)?;
+        Ok(WasmNamePart{part: NamePart::new_wordlist(name, &rules)})
+    }
+
+    /// Create a syllable part to learn from samples. The vowels are the default ones if left
+    /// out.
+    pub fn syllable(name: &str, rules: Vec<String>, vowels: Option<String>) -> Result<WasmNamePart, String> {
+        let rules = parse_rules(&rules)?;
+        let syllable = match vowels {
+            Some(vowels) => Syllable::with_vowels(&vowels),
+            None => Syllable::new(),
+        };
+
+        Ok(WasmNamePart{part: NamePart::new_syllable(name, &rules, syllable)})
+    }
+
+    pub fn pattern(name: &str, pattern: &str, rules: Vec<String>) -> Result<WasmNamePart, String> {
+        let rules = parse_rules(&rules)?;
+        let pattern = Pattern::parse(pattern)
+            .map_err(|e| format!("Invalid pattern: {}\n{}", e.message(), e.highlight(pattern)))?;
+
+        Ok(WasmNamePart{part: NamePart::new_pattern(name, &rules, pattern)})
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.part.name().to_owned()
+    }
+
+    /// Set the creativity of a markov part, and return false for other parts.
+    #[wasm_bindgen(js_name = setCreativity)]
+    pub fn set_creativity(&mut self, creativity: f64) -> bool {
+        self.part.set_creativity(creativity)
+    }
+
+    #[wasm_bindgen(js_name = setNormalization)]
+    pub fn set_normalization(&mut self, rules: Vec<String>) -> Result<(), String> {
+        let rules = rules.iter()
+            .map(|r| NormalizationRule::parse(r).ok_or_else(|| format!("Invalid normalization rule: {}", r)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.part.set_normalization(&rules);
+        Ok(())
+    }
+
+    /// Set the phonotactic rules. The classes are given as `name:letters`.
+    #[wasm_bindgen(js_name = setPhonotactics)]
+    pub fn set_phonotactics(&mut self, classes: Vec<String>, rules: Vec<String>) -> Result<(), String> {
+        let mut phonotactics = Phonotactics::new();
+        for class in classes.iter() {
+            let (name, chars) = class.split_once(':').ok_or_else(|| format!("Invalid class: {}", class))?;
+            phonotactics.add_class(name, chars);
+        }
+        for rule in rules.iter() {
+            phonotactics.add_rule(PhonotacticRule::parse(rule).ok_or_else(|| format!("Invalid phonotactic rule: {}", rule))?);
+        }
+        phonotactics.validate().map_err(|e| e.to_string())?;
+
+        self.part.set_phonotactics(phonotactics);
+        Ok(())
+    }
+}
+
+fn parse_rules(rules: &[String]) -> Result<Vec<FormattingRule>, String> {
+    rules.iter()
+        .map(|r| FormattingRule::parse(r).ok_or_else(|| format!("Invalid formatting rule: {}", r)))
+        .collect()
+}
+
+#[wasm_bindgen(js_name = SampleSet)]
+pub struct WasmSampleSet {
+    sample_set: SampleSet,
+}
+
+#[wasm_bindgen(js_class = SampleSet)]
+impl WasmSampleSet {
+    #[wasm_bindgen(constructor)]
+    pub fn new(labels: Vec<String>) -> WasmSampleSet {
+        WasmSampleSet{sample_set: SampleSet::with_labels(&labels)}
+    }
+
+    /// Parse a word list, with one word and an optional weight per line.
+    #[wasm_bindgen(js_name = parseWords)]
+    pub fn parse_words(data: &str) -> Result<WasmSampleSet, String> {
+        SampleSet::parse_words(data)
+            .map(|sample_set| WasmSampleSet{sample_set})
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parse a block file, which has one sample set per block.
+    #[wasm_bindgen(js_name = parseBlocks)]
+    pub fn parse_blocks(data: &str) -> Result<Vec<WasmSampleSet>, String> {
+        SampleSet::parse_blocks(data)
+            .map(|sets| sets.into_iter().map(|sample_set| WasmSampleSet{sample_set}).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    #[wasm_bindgen(js_name = addWord)]
+    pub fn add_word(&mut self, word: &str, weight: Option<u32>) {
+        self.sample_set.add_sample(match weight {
+            Some(weight) => Sample::WordWeighted(word.to_owned(), weight),
+            None => Sample::Word(word.to_owned()),
+        });
+    }
+
+    #[wasm_bindgen(js_name = addTokens)]
+    pub fn add_tokens(&mut self, tokens: Vec<String>) {
+        self.sample_set.add_sample(Sample::Tokens(tokens));
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.sample_set.samples().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings() {
+        let mut name = WasmName::new();
+        name.add_part(&WasmNamePart::markov("first", vec!["th".to_owned()], vec!["capitalize-first".to_owned()], false, false, true, false).unwrap()).unwrap();
+        name.add_part(&WasmNamePart::pattern("title", "<B><V>", vec![]).unwrap()).unwrap();
+        assert!(name.add_part(&WasmNamePart::wordlist("first", vec![]).unwrap()).is_err());
+        name.add_format("full", "{first} the {title}").unwrap();
+        assert!(name.add_format("full", "{first}").is_err());
+
+        let mut samples = WasmSampleSet::new(vec![]);
+        for word in ["aeris", "baldur", "celia", "thorin", "dorian"].iter() {
+            samples.add_word(word, None);
+        }
+        assert_eq!(samples.length(), 5);
+        name.learn("first", &samples).unwrap();
+        assert!(name.learn("last", &samples).is_err());
+        name.validate().unwrap();
+
+        let names = name.generate_seeded("full", 10, 42).unwrap();
+        assert_eq!(names.len(), 10);
+        assert_eq!(names, name.generate_seeded("full", 10, 42).unwrap());
+        assert!(names.iter().all(|n| n.contains(" the ")));
+        assert!(name.generate("last", 1).is_err());
+
+        let loaded = WasmName::from_json(&name.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.part_names(), vec!["first", "title"]);
+        assert_eq!(loaded.format_names(), vec!["full"]);
+        assert_eq!(loaded.generate_seeded("full", 10, 42).unwrap(), names);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(WasmNamePart::wordlist("first", vec!["capitalize".to_owned()]).is_err());
+        assert!(WasmNamePart::pattern("first", "<B", vec![]).err().unwrap().ends_with("<B\n^"));
+        assert!(WasmSampleSet::parse_words("aeris x").is_err());
+        assert!(WasmName::from_json("{").is_err());
+
+        let mut phonotactics = Phonotactics::new();
+        phonotactics.add_rule(PhonotacticRule::parse("require:liquid").unwrap());
+        let mut invalid = WasmName::new();
+        let mut part = NamePart::new_wordlist("first", &[]);
+        part.set_phonotactics(phonotactics);
+        invalid.name.add_part(part);
+        assert!(WasmName::from_json(&invalid.to_json().unwrap()).err().unwrap().contains("unknown class"));
+
+        let mut part = WasmNamePart::wordlist("first", vec![]).unwrap();
+        assert!(!part.set_creativity(0.5));
+        assert!(part.set_normalization(vec!["nfkc".to_owned()]).is_err());
+        assert!(part.set_phonotactics(vec![], vec!["require:liquid".to_owned()]).is_err());
+        part.set_phonotactics(vec!["liquid:lr".to_owned()], vec!["require:liquid".to_owned()]).unwrap();
+
+        let mut name = WasmName::new();
+        name.add_part(&part).unwrap();
+        name.learn("first", &WasmSampleSet::parse_words("tim\n").unwrap()).unwrap();
+        assert!(name.generate_part("first", 1).is_err());
+        assert_eq!(WasmSampleSet::parse_blocks("* *\nstone wall\n\n* *\niron forge\n").unwrap().len(), 2);
+    }
+}
@@ -1,6 +1,6 @@
 use rand::{Rng};
-use crate::{LearnError, WorkingSet, Sample, SampleSet};
-use crate::core::ValidationError;
+use crate::{LearnError, WorkingSet, Sample, SampleSet, Phonotactics};
+use crate::core::{ValidationError, GenerateError};
 
 /// WList is a simple word-list generator. It's probably not what you came here for, but some name
 /// parts are best filled with a word-list. It supports weighted words, so that you can make common
@@ -19,14 +19,38 @@ impl WordList {
     /// Generate a name. You need to provide your own WorkingSet and Rng, which is necessary to save
     /// on allocations. A dependent application should use the full name generator interface instead
     pub fn generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) {
-        if self.total_weight == 0 {
+        if self.try_generate(ws, rng).is_err() {
             ws.result_str.clear();
-            return;
+        }
+    }
+
+    /// Generate a name like `generate`, but return an error if the list is empty.
+    pub fn try_generate(&self, ws: &mut WorkingSet, rng: &mut impl Rng) -> Result<(), GenerateError> {
+        if self.total_weight == 0 {
+            return Err(GenerateError::new("parts::WordList", "Nothing has been learned."));
         }
 
         let roll: u32 = rng.gen_range(0, self.total_weight);
+        self.generate_with_roll(&mut ws.result_str, roll);
+
+        Ok(())
+    }
+
+    /// Generate a word that follows the phonotactic rules. Since a word can't be changed, this
+    /// draws again until one passes, and returns an error after `max_attempts` draws.
+    pub fn try_generate_with(&self, ws: &mut WorkingSet, rng: &mut impl Rng, max_attempts: usize, phonotactics: &Phonotactics) -> Result<(), GenerateError> {
+        if phonotactics.is_empty() {
+            return self.try_generate(ws, rng);
+        }
 
-        return self.generate_with_roll(&mut ws.result_str, roll);
+        for _ in 0..max_attempts {
+            self.try_generate(ws, rng)?;
+            if phonotactics.check(&ws.result_str) {
+                return Ok(());
+            }
+        }
+
+        Err(GenerateError::new("parts::WordList", "No valid output found within the attempt budget."))
     }
 
     fn generate_with_roll(&self, target: &mut String, roll: u32) {
@@ -52,6 +76,18 @@ impl WordList {
         }
     }
 
+    /// Get the likelihood of the word being generated, or `None` if it's not in the list.
+    pub fn score(&self, s: &str) -> Option<f64> {
+        self.rules.iter()
+            .find(|r| r.name == s)
+            .map(|r| r.weight as f64 / self.total_weight as f64)
+    }
+
+    /// Iterate over the words in the list.
+    pub fn outputs(&self) -> impl Iterator<Item=&str> {
+        self.rules.iter().map(|r| r.name.as_str())
+    }
+
     /// Learn learns samples from the sample set. The former state is copied and will
     /// be restored upon one of the samples failing to import.
     pub fn learn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError>  {
@@ -72,17 +108,17 @@ impl WordList {
 
         match sample {
             Sample::Word(w) => {
-                sample_word = &w;
+                sample_word = w;
                 sample_weight = 1;
             },
             Sample::WordWeighted(w, n) => {
-                sample_word = &w;
+                sample_word = w;
                 sample_weight = *n;
             },
             _ => {
                 return Err(LearnError::new(
                     1,
-                    format!("Incorrect sample type. Must be Word"),
+                    "Incorrect sample type. Must be Word".to_owned(),
                     Some(sample.clone()),
                 ));
             },
@@ -96,6 +132,55 @@ impl WordList {
         Ok(())
     }
 
+    /// Forget the samples, which takes away the weight they were learned with and removes words
+    /// that end up with none. The former state is restored if one of the samples was never
+    /// learned.
+    pub fn unlearn(&mut self, sample_set: &SampleSet) -> Result<(), LearnError> {
+        let old_state = self.clone();
+        for sample in sample_set.samples() {
+            if let Err(err) = self.unlearn_norebuild(sample) {
+                *self = old_state;
+                return Err(err);
+            }
+        }
+
+        self.fix_validation_issues();
+
+        Ok(())
+    }
+
+    fn unlearn_norebuild(&mut self, sample: &Sample) -> Result<(), LearnError> {
+        let (sample_word, sample_weight) = match sample {
+            Sample::Word(w) => (w, 1),
+            Sample::WordWeighted(w, n) => (w, *n),
+            _ => {
+                return Err(LearnError::new(
+                    1,
+                    "Incorrect sample type. Must be Word".to_owned(),
+                    Some(sample.clone()),
+                ));
+            },
+        };
+
+        let index = match self.rules.iter().position(|r| r.name == *sample_word) {
+            Some(index) if self.rules[index].weight >= sample_weight => index,
+            _ => {
+                return Err(LearnError::new(
+                    5,
+                    "Sample has not been learned".to_owned(),
+                    Some(sample.clone()),
+                ));
+            }
+        };
+
+        self.rules[index].weight -= sample_weight;
+        if self.rules[index].weight == 0 {
+            self.rules.remove(index);
+        }
+
+        Ok(())
+    }
+
     fn add_rule(&mut self, new_rule: Rule) {
         let mut index = self.rules.len();
         for (i, rule) in self.rules.iter().enumerate() {
@@ -186,6 +271,12 @@ impl WordList {
     }
 }
 
+impl Default for WordList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone, std::fmt::Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Rule {
@@ -229,6 +320,39 @@ mod tests {
         assert_eq!(&res, "artifact");
     }
 
+    #[test]
+    fn test_unlearn() {
+        let mut wlist = WordList::new();
+        let mut res = String::with_capacity(8);
+        wlist.learn_one(&sw("stuff", 1)).unwrap();
+        wlist.learn_one(&sw("things", 2)).unwrap();
+        wlist.learn_one(&sw("objects", 4)).unwrap();
+        wlist.learn_one(&sw("artifact", 1)).unwrap();
+
+        wlist.unlearn(&set(&[sw("objects", 3), s("stuff")])).unwrap();
+        wlist.validate().unwrap();
+        assert_eq!(wlist.outputs().collect::<Vec<_>>(), vec!["things", "objects", "artifact"]);
+        assert_eq!(wlist.score("things"), Some(0.5));
+        wlist.generate_with_roll(&mut res, 3);
+        assert_eq!(&res, "artifact");
+
+        assert!(wlist.unlearn(&set(&[s("things"), s("stuff")])).is_err());
+        assert!(wlist.unlearn(&set(&[sw("objects", 2)])).is_err());
+        assert_eq!(wlist.score("things"), Some(0.5));
+        wlist.validate().unwrap();
+    }
+
+    #[test]
+    fn test_score() {
+        let mut wlist = WordList::new();
+        wlist.learn_one(&sw("stuff", 3)).unwrap();
+        wlist.learn_one(&s("things")).unwrap();
+
+        assert_eq!(wlist.score("stuff"), Some(0.75));
+        assert_eq!(wlist.score("things"), Some(0.25));
+        assert_eq!(wlist.score("items"), None);
+    }
+
     #[test]
     fn test_cutoff() {
         let mut wlist = WordList::new();
@@ -294,6 +418,15 @@ mod tests {
         Sample::WordWeighted(s.to_owned(), w)
     }
 
+    fn set(samples: &[Sample]) -> SampleSet {
+        let mut sample_set = SampleSet::new();
+        for sample in samples.iter() {
+            sample_set.add_sample(sample.clone());
+        }
+
+        sample_set
+    }
+
     fn r(s: &str) -> Rule {
         Rule{name: s.to_owned(), weight: 1}
     }
@@ -0,0 +1,94 @@
+/* Exercises the C interface. Run by tests/capi.rs with a model as the argument. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "namegen.h"
+
+#define CHECK(cond)                                                      \
+    do {                                                                 \
+        if (!(cond)) {                                                   \
+            const char *err = namegen_last_error();                      \
+            fprintf(stderr, "%s:%d: %s (%s)\n", __FILE__, __LINE__,      \
+                    #cond, err ? err : "no error");                      \
+            return 1;                                                    \
+        }                                                                \
+    } while (0)
+
+static char *read_file(const char *path, size_t *len) {
+    FILE *f = fopen(path, "rb");
+    if (!f) {
+        return NULL;
+    }
+
+    fseek(f, 0, SEEK_END);
+    *len = (size_t)ftell(f);
+    fseek(f, 0, SEEK_SET);
+    char *data = malloc(*len);
+    if (data && fread(data, 1, *len, f) != *len) {
+        free(data);
+        data = NULL;
+    }
+    fclose(f);
+
+    return data;
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+
+    size_t len = 0;
+    char *data = read_file(argv[1], &len);
+    CHECK(data != NULL);
+
+    NamegenName *name = namegen_name_from_bytes((const uint8_t *)data, len);
+    free(data);
+    CHECK(name != NULL);
+
+    CHECK(namegen_iter_new(name, "missing", 0, 0) == NULL);
+    CHECK(strcmp(namegen_last_error(), "missing not found") == 0);
+    CHECK(namegen_name_from_json("{") == NULL);
+
+    /* The same seed gives the same names. */
+    char first[64], second[64];
+    NamegenIter *a = namegen_iter_new(name, "full", NAMEGEN_SEEDED, 1234);
+    NamegenIter *b = namegen_iter_new(name, "full", NAMEGEN_SEEDED, 1234);
+    CHECK(a != NULL && b != NULL);
+    for (int i = 0; i < 10; i++) {
+        CHECK(namegen_iter_next(a, first, sizeof(first), NULL) == NAMEGEN_OK);
+        CHECK(namegen_iter_next(b, second, sizeof(second), NULL) == NAMEGEN_OK);
+        CHECK(strcmp(first, second) == 0);
+        CHECK(strstr(first, " the ") != NULL);
+    }
+    namegen_iter_free(a);
+    namegen_iter_free(b);
+
+    /* The iterator outlives the model, and a small buffer can be retried. */
+    NamegenIter *iter = namegen_iter_new(name, "first", NAMEGEN_PART | NAMEGEN_UNIQUE, 0);
+    namegen_name_free(name);
+    CHECK(iter != NULL);
+
+    char tiny[2];
+    size_t needed = 0;
+    int count = 0;
+    for (;;) {
+        int res = namegen_iter_next(iter, tiny, sizeof(tiny), &needed);
+        if (res == NAMEGEN_DONE) {
+            break;
+        }
+        CHECK(res == NAMEGEN_BUFFER_TOO_SMALL);
+
+        char *buf = malloc(needed + 1);
+        size_t written = 0;
+        CHECK(namegen_iter_next(iter, buf, needed + 1, &written) == NAMEGEN_OK);
+        CHECK(written == needed && strlen(buf) == needed);
+        printf("%s\n", buf);
+        free(buf);
+        count++;
+    }
+    CHECK(count == 3);
+    namegen_iter_free(iter);
+
+    return 0;
+}
@@ -0,0 +1,51 @@
+//! Builds and runs `tests/capi.c` against the cdylib.
+
+#![cfg(all(feature = "capi", target_os = "linux"))]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use namegen::{Name, NamePart, Sample, SampleSet, FormattingRule};
+
+#[test]
+fn test_c_program() {
+    let mut name = Name::new();
+    name.add_part(NamePart::new_wordlist("first", &[FormattingRule::CapitalizeFirst]));
+    name.add_part(NamePart::new_pattern("title", &[FormattingRule::CapitalizeFirst], namegen::Pattern::parse("<B><V><C>").unwrap()));
+    name.add_format("full", "{first} the {title}").unwrap();
+
+    let mut samples = SampleSet::new();
+    for word in ["aeris", "baldur", "celia"].iter() {
+        samples.add_sample(Sample::Word((*word).to_owned()));
+    }
+    name.learn("first", &samples).unwrap();
+
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    // The cdylib is built next to the test executable in `deps`.
+    let lib_dir = std::env::current_exe().unwrap().parent().unwrap().to_owned();
+    let model_path = tmp_dir.join("capi_model.json");
+    let exe_path = tmp_dir.join("capi_test");
+    std::fs::write(&model_path, serde_json::to_string(&name).unwrap()).unwrap();
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .arg(manifest_dir.join("tests/capi.c"))
+        .arg("-I").arg(manifest_dir.join("include"))
+        .arg("-L").arg(&lib_dir)
+        .arg("-lnamegen")
+        .arg("-o").arg(&exe_path)
+        .status()
+        .expect("a C compiler is needed to test the C interface");
+    assert!(status.success());
+
+    let output = Command::new(&exe_path)
+        .arg(&model_path)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut names: Vec<String> = String::from_utf8(output.stdout).unwrap().lines().map(|s| s.to_owned()).collect();
+    names.sort();
+    assert_eq!(names, vec!["Aeris", "Baldur", "Celia"]);
+}